@@ -15,3 +15,15 @@ pub enum Artifact {
     Ast(Node),
     AstPointer(Weak<Mutex<Node>>),
 }
+
+impl Artifact {
+    /// Get size of artifact content in bytes
+    pub fn size(&self) -> Option<u64> {
+        match self {
+            Artifact::Path(path) => std::fs::metadata(path).ok().map(|m| m.len()),
+            Artifact::Plaintext(_, content) => Some(content.len() as u64),
+            Artifact::Binary(_, content) => Some(content.len() as u64),
+            Artifact::Ast(_) | Artifact::AstPointer(_) => None,
+        }
+    }
+}
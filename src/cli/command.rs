@@ -1,17 +1,17 @@
 use std::{fmt::Display, path::PathBuf};
 
-use clap::{Parser, ValueEnum};
+use clap::{Parser, Subcommand, ValueEnum};
 use futures::StreamExt;
 
 use super::{
     reader::Reader,
-    types::{AppError, Event, EventTx, Output},
+    types::{AppError, AssetMode, Event, EventTx, Output},
 };
 
 #[derive(Parser, Debug, Default)]
 #[command(author, version, about, long_about = None)]
 pub(crate) struct Config {
-    /// Output format
+    /// Output format, or comma separated formats where the first one is primary
     #[clap(short, long = "format", default_value = "html", value_parser = clap::builder::NonEmptyStringValueParser::new(), global = true)]
     pub format: Option<String>,
 
@@ -26,6 +26,38 @@ pub(crate) struct Config {
     // NOTE: default value set in `Config::validate`
     pub output: Option<Output>,
 
+    /// Base URL for generating a sitemap and feed
+    #[clap(long, value_name = "URL", global = true)]
+    pub base_url: Option<String>,
+
+    /// Title of the generated feed
+    #[clap(long, value_name = "TITLE", global = true)]
+    pub feed_title: Option<String>,
+
+    /// Embed referenced assets, stylesheets and scripts into compiled documents
+    #[clap(long, global = true)]
+    pub inline_assets: bool,
+
+    /// Remove outputs of previous builds that were not produced again
+    #[clap(long, global = true)]
+    pub clean: bool,
+
+    /// Copy assets to content-hashed names and write a manifest
+    #[clap(long, conflicts_with = "inline_assets", global = true)]
+    pub fingerprint: bool,
+
+    /// Print a summary of slowest operations and totals per operation kind
+    #[clap(long, global = true)]
+    pub timings: bool,
+
+    /// Write a JSON report of operation durations and artifact sizes
+    #[clap(long, value_name = "FILE", global = true)]
+    pub timings_report: Option<PathBuf>,
+
+    /// Theme directory with a lang overlay and static assets
+    #[clap(long, value_name = "DIR", global = true)]
+    pub theme: Option<PathBuf>,
+
     /// Log format
     ///
     /// [default: auto, possible values: auto, html, plain]
@@ -81,16 +113,61 @@ pub(crate) enum Command {
         #[arg(default_values_t = ["ROOT".to_string(), "DOCUMENT".to_string()])]
         splits: Vec<String>,
 
+        /// Print planned operations without running commands or writing files
+        #[clap(long)]
+        dry_run: bool,
+
         /// Input paths or data URLs
         #[clap(value_name = "PATH")]
         #[arg(default_values_t = [".".to_string()])]
         paths: Vec<String>,
     },
+    /// Extract code blocks to source files
+    Tangle {
+        /// Input paths or data URLs
+        #[clap(value_name = "PATH")]
+        #[arg(default_values_t = [".".to_string()])]
+        paths: Vec<String>,
+    },
+    /// Inspect available langs
+    Lang {
+        #[command(subcommand)]
+        command: LangCommand,
+    },
     /// Exit interactive mode
     #[clap(hide = true)]
     Exit,
 }
 
+#[derive(Subcommand, Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(test, derive(Ord, PartialOrd))]
+pub(crate) enum LangCommand {
+    /// List builtin langs and langs found in the search path
+    List,
+    /// Print source of a lang
+    Show {
+        /// Lang name
+        name: String,
+    },
+    /// Write a builtin lang to a file as a starting point for customization
+    Eject {
+        /// Builtin lang name
+        name: String,
+
+        /// Target path
+        ///
+        /// [default: langs/<NAME>.lang]
+        path: Option<PathBuf>,
+    },
+    /// Check langs for unknown instructions, unused rules and unbalanced stacks
+    Check {
+        /// Lang names
+        ///
+        /// [default: all available langs]
+        names: Vec<String>,
+    },
+}
+
 impl Config {
     pub fn defaults(mut self) -> Self {
         // workaround for `default_value_ifs` issue in clap
@@ -102,6 +179,38 @@ impl Config {
         };
         self
     }
+
+    /// Get output formats, the first one being the primary format
+    pub fn formats(&self) -> Vec<&str> {
+        let format = self.format.as_deref().expect("format");
+        format.split(',').map(str::trim).filter(|f| !f.is_empty()).collect()
+    }
+
+    /// Get primary output format
+    pub fn primary_format(&self) -> &str {
+        self.formats().first().copied().unwrap_or("html")
+    }
+
+    /// Check whether outputs are tracked by a build manifest, which is not written to the
+    /// current directory
+    pub fn tracks_outputs(&self) -> bool {
+        let Some(Output::Path(root)) = &self.output else {
+            return false;
+        };
+        let cwd = std::env::current_dir().and_then(std::fs::canonicalize);
+        match (std::fs::canonicalize(root), cwd) {
+            (Ok(root), Ok(cwd)) => root != cwd,
+            _ => true,
+        }
+    }
+
+    pub fn asset_mode(&self) -> AssetMode {
+        match (self.inline_assets, self.fingerprint) {
+            (true, _) => AssetMode::Inline,
+            (_, true) => AssetMode::Fingerprint,
+            _ => AssetMode::Copy,
+        }
+    }
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum, PartialOrd, Ord)]
@@ -0,0 +1,66 @@
+use std::path::{Path, PathBuf};
+
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+
+pub static MANIFEST: &str = "asset-manifest.json";
+
+/// Hash content with SHA-256 as hex
+pub fn hash(content: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(content))
+}
+
+/// Insert a short content hash into a file name (eg. `img1.png` to `img1.3f9a2c1b.png`)
+pub fn hashed_path(path: &Path, content: &[u8]) -> PathBuf {
+    let hash = &hash(content)[..8];
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or_default();
+    let name = match path.extension().and_then(|e| e.to_str()) {
+        Some(ext) => format!("{stem}.{hash}.{ext}"),
+        None => format!("{stem}.{hash}"),
+    };
+    path.with_file_name(name)
+}
+
+/// Build JSON manifest mapping original to fingerprinted paths
+pub fn manifest(entries: &[(String, String)]) -> String {
+    let map = entries
+        .iter()
+        .map(|(from, to)| (from.clone(), Value::from(to.as_str())))
+        .collect::<Map<_, _>>();
+    let mut out = serde_json::to_string_pretty(&map).expect("serializable manifest");
+    out.push('\n');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_hashed_path() {
+        assert_eq!(&hash(b"")[..16], "e3b0c44298fc1c14");
+        assert_eq!(&hash(b"a")[..16], "ca978112ca1bbdca");
+        assert_eq!(hashed_path(Path::new("img/a.png"), b"a"), Path::new("img/a.ca978112.png"));
+        assert_eq!(hashed_path(Path::new("LICENSE"), b"a"), Path::new("LICENSE.ca978112"));
+    }
+
+    #[test]
+    fn test_manifest() {
+        let entries = vec![
+            ("a.png".to_string(), "a.ca978112.png".to_string()),
+            ("b \"quoted\".png".to_string(), "b \"quoted\".e3b0c442.png".to_string()),
+        ];
+        assert_eq!(
+            manifest(&entries),
+            indoc! {r#"
+            {
+              "a.png": "a.ca978112.png",
+              "b \"quoted\".png": "b \"quoted\".e3b0c442.png"
+            }
+            "#}
+        );
+    }
+}
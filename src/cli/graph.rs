@@ -138,7 +138,7 @@ mod tests {
 
         let mut result = vec![];
         for (from, _vertex, edges) in graph.iter() {
-            let deps = edges.iter().cloned().collect::<Vec<_>>();
+            let deps = edges.to_vec();
             result.push((from, deps));
         }
         result.sort();
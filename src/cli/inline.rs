@@ -0,0 +1,177 @@
+use std::collections::HashSet;
+use std::path::Path;
+use std::sync::{Arc, LazyLock};
+
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use murkdown::{ast::Node, types::Pointer};
+use regex::{Captures, Regex};
+
+static STYLESHEET: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"<link rel="stylesheet" href="([^"]*)"[^>]*>"#).expect("valid regex")
+});
+static SCRIPT: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"<script src="([^"]*)"></script>"#).expect("valid regex"));
+
+/// Encode content as a data URL
+pub fn data_url(media_type: &str, content: &[u8]) -> String {
+    format!("data:{media_type};base64,{}", STANDARD.encode(content))
+}
+
+/// Guess media type of an asset from its extension
+pub fn media_type(path: &Path) -> &'static str {
+    let ext = path.extension().and_then(|e| e.to_str()).unwrap_or_default();
+    match ext.to_ascii_lowercase().as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "svg" => "image/svg+xml",
+        "webp" => "image/webp",
+        "ico" => "image/x-icon",
+        "css" => "text/css",
+        "js" | "mjs" => "text/javascript",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "ttf" => "font/ttf",
+        "pdf" => "application/pdf",
+        "txt" => "text/plain",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Check if a reference points to a local asset
+pub fn is_local(href: &str) -> bool {
+    !(href.is_empty()
+        || href.starts_with('#')
+        || href.starts_with("//")
+        || href.starts_with("data:")
+        || href.split_once("://").is_some())
+}
+
+/// Replace `ref` props with resolved values, following include pointers
+pub fn rewrite_refs<F>(node: &mut Node, resolve: &mut F)
+where
+    F: FnMut(&str) -> Option<String>,
+{
+    rewrite_refs_recursive(node, resolve, &mut HashSet::new());
+}
+
+fn rewrite_refs_recursive<F>(node: &mut Node, resolve: &mut F, visited: &mut HashSet<usize>)
+where
+    F: FnMut(&str) -> Option<String>,
+{
+    if let Some(props) = node.props.as_mut() {
+        for (_, value) in props.iter_mut().filter(|(k, _)| &**k == "ref") {
+            if let Some(url) = resolve(value) {
+                *value = Arc::from(url);
+            }
+        }
+    }
+
+    if let Some(Pointer(weak)) = &node.pointer {
+        // NOTE: each included node is only visited once
+        if let Some(arc) = weak.upgrade() {
+            if visited.insert(Arc::as_ptr(&arc) as usize) {
+                let mut node = arc.lock().expect("poisoned lock");
+                rewrite_refs_recursive(&mut node, resolve, visited);
+            }
+        }
+    }
+
+    for child in node.children.iter_mut().flatten() {
+        rewrite_refs_recursive(child, resolve, visited);
+    }
+}
+
+/// Bundle linked stylesheets and scripts into an HTML document
+pub fn inline_html<F>(html: &str, resolve: &mut F) -> String
+where
+    F: FnMut(&str) -> Option<String>,
+{
+    let html = STYLESHEET.replace_all(html, |caps: &Captures| match resolve(&caps[1]) {
+        Some(content) => format!("<style>{content}</style>"),
+        None => caps[0].to_string(),
+    });
+    let html = SCRIPT.replace_all(&html, |caps: &Captures| match resolve(&caps[1]) {
+        Some(content) => format!("<script>{content}</script>"),
+        None => caps[0].to_string(),
+    });
+    html.into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use murkdown::ast::NodeBuilder;
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_data_url() {
+        assert_eq!(data_url("text/plain", b"hello"), "data:text/plain;base64,aGVsbG8=");
+        assert_eq!(media_type(Path::new("img/a.PNG")), "image/png");
+        assert!(is_local("img/a.png"));
+        assert!(!is_local("https://example.com/a.png"));
+        assert!(!is_local("data:text/plain;base64,"));
+    }
+
+    #[test]
+    fn test_rewrite_refs_follows_pointers() {
+        let included = NodeBuilder::root()
+            .add_section(vec![NodeBuilder::block(">")
+                .add_prop(("ref".into(), "b.png".into()))
+                .done()])
+            .done();
+        let arc = Arc::new(Mutex::new(included));
+        let mut node = NodeBuilder::root()
+            .add_section(vec![
+                NodeBuilder::block(">")
+                    .add_prop(("ref".into(), "a.png".into()))
+                    .done(),
+                NodeBuilder::block(">")
+                    .add_prop(("ref".into(), "https://example.com/c.png".into()))
+                    .done(),
+                Node::ellipsis(Some(Pointer(Arc::downgrade(&arc)))),
+            ])
+            .done();
+
+        rewrite_refs(&mut node, &mut |href| {
+            is_local(href).then(|| format!("data:{href}"))
+        });
+
+        let section = node.children.as_ref().unwrap().first().unwrap();
+        let refs = section
+            .children
+            .iter()
+            .flatten()
+            .filter_map(|n| n.find_prop("ref"))
+            .collect::<Vec<_>>();
+        assert_eq!(refs, vec![Arc::from("data:a.png"), Arc::from("https://example.com/c.png")]);
+
+        let included = arc.lock().unwrap();
+        let section = included.children.as_ref().unwrap().first().unwrap();
+        let block = section.children.as_ref().unwrap().first().unwrap();
+        assert_eq!(block.find_prop("ref"), Some(Arc::from("data:b.png")));
+    }
+
+    #[test]
+    fn test_inline_html() {
+        let html = concat!(
+            "<link rel=\"stylesheet\" href=\"style.css\">\n",
+            "<link rel=\"stylesheet\" href=\"https://example.com/remote.css\">\n",
+            "<script src=\"main.js\"></script>\n",
+        );
+        let result = inline_html(html, &mut |href| {
+            is_local(href).then(|| format!("/* {href} */"))
+        });
+        assert_eq!(
+            result,
+            concat!(
+                "<style>/* style.css */</style>\n",
+                "<link rel=\"stylesheet\" href=\"https://example.com/remote.css\">\n",
+                "<script>/* main.js */</script>\n",
+            )
+        );
+    }
+}
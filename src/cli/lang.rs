@@ -0,0 +1,258 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use hashbrown::HashMap;
+use log::{info, warn};
+use murkdown::compiler::{self, Lang};
+
+use super::types::{AppError, AppErrorPathCtx, LangMap};
+
+/// Environment variable listing additional lang directories
+pub static PATH_VAR: &str = "MURKDOWN_LANG_PATH";
+static EXTENSION: &str = "lang";
+static DIR: &str = "langs";
+
+/// Version of murkdown that builtin langs are shipped with
+pub static VERSION: &str = env!("CARGO_PKG_VERSION");
+
+pub static BUILTIN: &[(&str, &str)] = &[
+    ("markdown", include_str!("../lib/compiler/markdown.lang")),
+    ("html", include_str!("../lib/compiler/html.lang")),
+    ("plaintext", include_str!("../lib/compiler/plaintext.lang")),
+];
+
+/// Available lang along with where it was found
+#[derive(Debug)]
+pub struct LangEntry {
+    pub name: String,
+    pub path: Option<PathBuf>,
+    pub lang: Result<Lang, AppError>,
+}
+
+/// Get directories searched for custom langs, in order of precedence
+pub fn search_path() -> Vec<PathBuf> {
+    let mut dirs = vec![PathBuf::from("."), PathBuf::from(DIR)];
+    if let Some(paths) = std::env::var_os(PATH_VAR) {
+        let paths = std::env::split_paths(&paths).filter(|p| !p.as_os_str().is_empty());
+        dirs.extend(paths);
+    }
+    if let Some(dir) = config_dir() {
+        dirs.push(dir.join("murkdown").join(DIR));
+    }
+    dirs
+}
+
+fn config_dir() -> Option<PathBuf> {
+    let var = |name| std::env::var_os(name).filter(|v| !v.is_empty()).map(PathBuf::from);
+    var("XDG_CONFIG_HOME")
+        .or_else(|| var("HOME").map(|home| home.join(".config")))
+        .or_else(|| var("APPDATA"))
+}
+
+/// Find custom lang file from the search path
+pub fn find(name: &str) -> Option<PathBuf> {
+    search_path()
+        .into_iter()
+        .map(|dir| dir.join(format!("{name}.{EXTENSION}")))
+        .find(|path| path.is_file())
+}
+
+/// Get source of a builtin lang
+pub fn builtin(name: &str) -> Option<&'static str> {
+    BUILTIN.iter().find_map(|(n, content)| (*n == name).then_some(*content))
+}
+
+/// Get source of a lang, preferring custom langs over builtin ones
+pub fn source(name: &str) -> Result<String, AppError> {
+    match find(name) {
+        Some(path) => std::fs::read_to_string(&path).with_ctx(path),
+        None => builtin(name)
+            .map(String::from)
+            .ok_or_else(|| AppError::unknown_language(name)),
+    }
+}
+
+/// Write a builtin lang to a new file, marked with the version it was ejected from
+pub fn eject(name: &str, path: Option<&Path>) -> Result<PathBuf, AppError> {
+    let content = builtin(name).ok_or_else(|| AppError::unknown_language(name))?;
+    let path = match path {
+        Some(path) => path.to_path_buf(),
+        None => Path::new(DIR).join(format!("{name}.{EXTENSION}")),
+    };
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent).map_err(|e| AppError::write_error(e, parent))?;
+    }
+
+    // NOTE: never overwrite a lang that may have been customized already
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&path)
+        .map_err(|e| AppError::write_error(e, &path))?;
+    let header = format!("/* Ejected from builtin {name} lang of murkdown {VERSION} */\n");
+    file.write_all(header.as_bytes())
+        .and_then(|_| file.write_all(content.as_bytes()))
+        .map_err(|e| AppError::write_error(e, &path))?;
+    Ok(path)
+}
+
+/// Parse builtin langs
+pub fn builtins() -> Result<LangMap, AppError> {
+    let mut languages = HashMap::new();
+    for (name, content) in BUILTIN {
+        languages.insert(name.to_string(), Lang::new(content)?);
+    }
+    Ok(languages)
+}
+
+/// Load custom lang from the search path along with the langs it extends
+pub fn load(
+    languages: &mut LangMap,
+    name: &str,
+    descendants: &mut Vec<String>,
+) -> Result<(), AppError> {
+    if descendants.iter().any(|d| d == name) {
+        descendants.push(name.to_string());
+        return Err(AppError::cyclic_language(descendants.join(" -> ")));
+    }
+
+    // NOTE: custom langs replace builtin langs of the same name
+    let Some(path) = find(name) else {
+        return match languages.contains_key(name) {
+            true => Ok(()),
+            false => Err(AppError::unknown_language(name)),
+        };
+    };
+    let mut lang = read(&path)?;
+    if let Some(parent) = lang.extends.clone() {
+        descendants.push(name.to_string());
+        load(languages, &parent, descendants)?;
+        lang.extend(&languages[&parent]);
+    }
+    languages.insert(name.to_string(), lang);
+    Ok(())
+}
+
+/// Read and parse a lang file
+pub fn read(path: &Path) -> Result<Lang, AppError> {
+    let content = std::fs::read_to_string(path).with_ctx(path)?;
+    Lang::new(&content).map_err(|source| AppError::bad_language(source, path))
+}
+
+/// Get names of builtin langs followed by custom langs, along with paths of custom langs
+///
+/// Custom langs replace builtin langs of the same name, and shadow custom langs later in the
+/// search path.
+pub fn discover() -> Vec<(String, Option<PathBuf>)> {
+    let mut entries = BUILTIN
+        .iter()
+        .map(|(name, _)| (name.to_string(), None))
+        .collect::<Vec<_>>();
+
+    for dir in search_path() {
+        let Ok(dir_entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        let mut paths = dir_entries
+            .filter_map(Result::ok)
+            .map(|e| e.path())
+            .filter(|p| p.is_file() && p.extension().is_some_and(|e| e == EXTENSION))
+            .collect::<Vec<_>>();
+        paths.sort();
+
+        for path in paths {
+            let Some(name) = path.file_stem().and_then(|s| s.to_str()) else {
+                continue;
+            };
+            match entries.iter_mut().find(|(n, _)| n == name) {
+                Some((_, Some(_))) => {}
+                Some((_, builtin)) => *builtin = Some(path),
+                None => entries.push((name.to_string(), Some(path))),
+            }
+        }
+    }
+    entries
+}
+
+/// Get available langs, along with the errors of langs that could not be read
+pub fn available() -> Vec<LangEntry> {
+    discover()
+        .into_iter()
+        .map(|(name, path)| {
+            let lang = match &path {
+                Some(path) => read(path),
+                None => Lang::new(builtin(&name).expect("builtin lang")).map_err(Into::into),
+            };
+            LangEntry { name, path, lang }
+        })
+        .collect()
+}
+
+/// Check langs, or all available langs if none are given, and get the number of problems
+pub fn check(names: &[String]) -> Result<usize, AppError> {
+    let entries = match names.is_empty() {
+        true => discover(),
+        false => names.iter().map(|name| (name.clone(), find(name))).collect(),
+    };
+
+    let mut problems = 0;
+    for (name, path) in entries {
+        let (label, content) = match &path {
+            Some(path) => {
+                let content = std::fs::read_to_string(path).with_ctx(path)?;
+                (path.display().to_string(), content)
+            }
+            None => {
+                let content = builtin(&name).ok_or_else(|| AppError::unknown_language(&name))?;
+                (format!("{name} (builtin)"), content.to_string())
+            }
+        };
+
+        let mut report = |message: &dyn std::fmt::Display| {
+            warn!("{label}: {message}");
+            problems += 1;
+        };
+        let lang = match Lang::new(&content) {
+            Ok(lang) => lang,
+            Err(err) => {
+                report(&err);
+                continue;
+            }
+        };
+
+        let mut languages = builtins()?;
+        if let Some(parent) = &lang.extends {
+            if let Err(err) = load(&mut languages, parent, &mut vec![name.clone()]) {
+                report(&err);
+                continue;
+            }
+        }
+        let parent = lang.extends.as_ref().and_then(|p| languages.get(p));
+
+        let diagnostics = compiler::check(&lang, parent);
+        for diagnostic in &diagnostics {
+            report(diagnostic);
+        }
+        if diagnostics.is_empty() {
+            info!("{label}: ok");
+        }
+    }
+    Ok(problems)
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_builtin_langs() {
+        for (name, content) in BUILTIN {
+            let lang = Lang::new(content).expect("valid builtin lang");
+            assert_eq!(lang.name, *name);
+        }
+        assert_eq!(builtin("html"), Some(BUILTIN[1].1));
+        assert_eq!(builtin("unknown"), None);
+    }
+}
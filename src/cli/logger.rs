@@ -1,4 +1,5 @@
 use std::io::{self, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 use env_logger::fmt::Formatter;
 use env_logger::{Builder, Target};
@@ -6,6 +7,9 @@ use log::{kv::Key, LevelFilter, Record};
 
 use super::command::Config;
 
+/// Number of warnings logged so far
+pub static WARNINGS: AtomicUsize = AtomicUsize::new(0);
+
 pub fn setup_logging(config: &Config) {
     let level = match config.verbosity {
         0 => LevelFilter::Info,
@@ -18,7 +22,12 @@ pub fn setup_logging(config: &Config) {
         _ => default_formatter,
     };
     Builder::new()
-        .format(formatter)
+        .format(move |buf, record| {
+            if record.level() == log::Level::Warn {
+                WARNINGS.fetch_add(1, Ordering::Relaxed);
+            }
+            formatter(buf, record)
+        })
         .filter_level(level)
         .target(Target::Stdout)
         .init();
@@ -0,0 +1,100 @@
+use std::path::{Component, Path, PathBuf};
+
+use itertools::Itertools;
+
+pub static FILE: &str = ".md-manifest";
+
+/// Output file recorded by a build
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ManifestEntry {
+    pub uri: String,
+    pub path: String,
+    pub hash: String,
+}
+
+/// Render manifest as tab separated lines of hash, output path and source URI
+pub fn render(entries: &[ManifestEntry]) -> String {
+    entries
+        .iter()
+        .map(|e| format!("{}\t{}\t{}\n", e.hash, e.path, e.uri))
+        .join("")
+}
+
+/// Parse manifest, skipping malformed lines
+pub fn parse(content: &str) -> Vec<ManifestEntry> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.splitn(3, '\t');
+            let hash = fields.next()?.to_string();
+            let path = fields.next()?.to_string();
+            let uri = fields.next()?.to_string();
+            Some(ManifestEntry { uri, path, hash })
+        })
+        .collect()
+}
+
+/// Get entries of a previous manifest that were not produced again, although their source was
+/// built or no longer exists
+pub fn stale<'a>(
+    previous: &'a [ManifestEntry],
+    current: &[ManifestEntry],
+    sources: &[&Path],
+) -> Vec<&'a ManifestEntry> {
+    previous
+        .iter()
+        .filter(|prev| current.iter().all(|e| e.path != prev.path))
+        .filter(|prev| match source(&prev.uri) {
+            Some(path) => sources.iter().any(|s| path.starts_with(s)) || !path.exists(),
+            None => false,
+        })
+        // NOTE: never touch files outside of the output directory
+        .filter(|prev| {
+            let path = Path::new(&prev.path);
+            path.components().all(|c| matches!(c, Component::Normal(_)))
+        })
+        .collect()
+}
+
+/// Get source path of an output URI (eg. `write+markdown:a.md` to `a.md`)
+fn source(uri: &str) -> Option<PathBuf> {
+    let (_, path) = uri.split_once(':')?;
+    let path = path.split_once('#').map_or(path, |(path, _)| path);
+    Some(Path::new(path).components().filter(|c| *c != Component::CurDir).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn entry(uri: &str, path: &str) -> ManifestEntry {
+        ManifestEntry { uri: uri.to_string(), path: path.to_string(), hash: "00".to_string() }
+    }
+
+    #[test]
+    fn test_render_and_parse() {
+        let entries = vec![entry("write:a.md", "a.html"), entry("copy:img/b.png", "img/b.png")];
+        let content = render(&entries);
+        assert_eq!(content, "00\ta.html\twrite:a.md\n00\timg/b.png\tcopy:img/b.png\n");
+        assert_eq!(parse(&format!("{content}malformed\n")), entries);
+    }
+
+    #[test]
+    fn test_stale() {
+        let previous = vec![
+            entry("write:a.md", "a.html"),
+            entry("write:old.md", "old.html"),
+            entry("copy:../secret", "../secret"),
+            entry("write:docs/old.md", "docs/old.html"),
+            entry("write:Cargo.toml", "Cargo.html"),
+        ];
+        let current = vec![entry("write:a.md", "a.html")];
+        assert_eq!(stale(&previous, &current, &[]), vec![&previous[1], &previous[3]]);
+        assert_eq!(
+            stale(&previous, &current, &[Path::new("")]),
+            vec![&previous[1], &previous[3], &previous[4]]
+        );
+    }
+}
@@ -1,12 +1,19 @@
 mod artifact;
 pub(crate) mod command;
+mod fingerprint;
 mod graph;
 mod graph_sorter;
+mod inline;
+mod lang;
 pub(crate) mod logger;
+mod manifest;
 mod op;
 pub(crate) mod reader;
+mod site;
 pub(crate) mod state;
 mod state_context;
 pub(crate) mod task;
+mod theme;
+mod timings;
 pub(crate) mod types;
 pub(crate) mod utils;
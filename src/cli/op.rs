@@ -23,6 +23,10 @@ pub enum Op {
     CompilePlaintext,
     Write,
     Copy,
+    Theme,
+    Fingerprint,
+    Site,
+    Manifest,
     Graph,
     Finish,
 }
@@ -41,6 +45,10 @@ impl From<&Operation> for Op {
             CompilePlaintext { .. } => Op::CompilePlaintext,
             Write { .. } => Op::Write,
             Copy { .. } => Op::Copy,
+            Theme { .. } => Op::Theme,
+            Fingerprint => Op::Fingerprint,
+            Site { .. } => Op::Site,
+            Manifest { .. } => Op::Manifest,
             Graph { .. } => Op::Graph,
             Finish => Op::Finish,
         }
@@ -55,6 +63,7 @@ pub enum Operation {
         sources: Vec<Source>,
         #[allow(dead_code)]
         splits: Option<Vec<String>>,
+        formats: Vec<Option<Id>>,
     },
     Exec {
         id: Id,
@@ -66,19 +75,23 @@ pub enum Operation {
         id: Id,
         source: Source,
     },
+    /// Tangle to target path, or tangle document blocks to output if no target
     Tangle {
         id: Id,
-        target: PathBuf,
+        target: Option<PathBuf>,
     },
     Parse {
         id: Id,
     },
+    /// Preprocess for a secondary format, or the primary format if none
     Preprocess {
         id: Id,
         headers: Option<String>,
+        format: Option<Id>,
     },
     Compile {
         id: Id,
+        format: Option<Id>,
     },
     CompilePlaintext {
         id: Id,
@@ -86,11 +99,24 @@ pub enum Operation {
     },
     Write {
         id: Id,
+        format: Option<Id>,
     },
     Copy {
         id: Id,
         source: Source,
     },
+    Theme {
+        name: Id,
+    },
+    Fingerprint,
+    Site {
+        base_url: String,
+        title: String,
+    },
+    Manifest {
+        clean: bool,
+        sources: Vec<Source>,
+    },
     Finish,
     Graph {
         graph_type: GraphType,
@@ -105,6 +131,25 @@ impl Operation {
     pub fn uri_path(&self) -> URI {
         OpId::from(self).uri_path()
     }
+
+    /// Get secondary format of a format specific operation
+    pub fn format(&self) -> Option<&Id> {
+        match self {
+            Operation::Preprocess { format, .. }
+            | Operation::Compile { format, .. }
+            | Operation::Write { format, .. } => format.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// Check if operation is only planned and not executed during a dry run
+    pub fn is_planned(&self) -> bool {
+        use Operation::*;
+        !matches!(
+            self,
+            Gather { .. } | Load { .. } | Parse { .. } | Preprocess { .. } | Graph { .. } | Finish
+        )
+    }
 }
 
 impl Display for Operation {
@@ -115,67 +160,123 @@ impl Display for Operation {
             Operation::Load { id, .. } => write!(f, "Load {}", id),
             Operation::Tangle { id, .. } => write!(f, "Tangle {}", id),
             Operation::Parse { id, .. } => write!(f, "Parse {}", id),
+            Operation::Preprocess { id, format: Some(format), .. } => {
+                write!(f, "Preprocess {} as {}", id, format)
+            }
             Operation::Preprocess { id, .. } => write!(f, "Preprocess {}", id),
             Operation::CompilePlaintext { id, .. } => write!(f, "Compile plaintext {}", id),
+            Operation::Compile { id, format: Some(format) } => {
+                write!(f, "Compile {} as {}", id, format)
+            }
             Operation::Compile { id, .. } => write!(f, "Compile {}", id),
+            Operation::Write { id, format: Some(format) } => {
+                write!(f, "Write {} as {}", id, format)
+            }
             Operation::Write { id, .. } => write!(f, "Write {}", id),
             Operation::Copy { id, .. } => write!(f, "Copy {}", id),
+            Operation::Theme { name } => write!(f, "Theme {}", name),
+            Operation::Fingerprint => write!(f, "Fingerprint"),
+            Operation::Site { .. } => write!(f, "Site"),
+            Operation::Manifest { .. } => write!(f, "Manifest"),
             Operation::Graph { .. } => write!(f, "Graph"),
             Operation::Finish => write!(f, "Finish"),
         }
     }
 }
 
+/// Qualify scheme of a format specific URI (eg. `parse:a.md` to `parse+markdown:a.md`)
+pub fn qualify(uri: &str, format: Option<&str>) -> String {
+    match (format, uri.split_once(':')) {
+        (Some(format), Some((scheme @ ("parse" | "compile" | "write"), path))) => {
+            format!("{scheme}+{format}:{path}")
+        }
+        _ => uri.to_string(),
+    }
+}
+
+/// Remove format qualifier from scheme of a URI
+pub fn unqualify(uri: &str) -> String {
+    match uri.split_once(':') {
+        Some((scheme, path)) if scheme.contains('+') => {
+            let (scheme, _) = scheme.split_once('+').expect("qualified scheme");
+            format!("{scheme}:{path}")
+        }
+        _ => uri.to_string(),
+    }
+}
+
 #[derive(Debug, Eq, PartialEq, Hash, Ord, PartialOrd, Clone)]
-pub struct OpId(Op, Id);
+pub struct OpId(Op, Id, Option<Id>);
 
 impl OpId {
     pub fn gather() -> Self {
-        Self(Op::Gather, Arc::from("Gather"))
+        Self(Op::Gather, Arc::from("Gather"), None)
     }
 
     pub fn exec(id: impl Into<Arc<str>>) -> Self {
-        Self(Op::Exec, id.into())
+        Self(Op::Exec, id.into(), None)
     }
 
     #[cfg(test)]
     pub fn load(id: impl Into<Arc<str>>) -> Self {
-        Self(Op::Load, id.into())
+        Self(Op::Load, id.into(), None)
+    }
+
+    pub fn tangle(id: impl Into<Arc<str>>) -> Self {
+        Self(Op::Tangle, id.into(), None)
     }
 
-    #[cfg(test)]
     pub fn parse(id: impl Into<Arc<str>>) -> Self {
-        Self(Op::Parse, id.into())
+        Self(Op::Parse, id.into(), None)
     }
 
     #[cfg(test)]
     pub fn preprocess(id: impl Into<Arc<str>>) -> Self {
-        Self(Op::Preprocess, id.into())
+        Self(Op::Preprocess, id.into(), None)
     }
 
     #[cfg(test)]
     pub fn compile(id: impl Into<Arc<str>>) -> Self {
-        Self(Op::Compile, id.into())
+        Self(Op::Compile, id.into(), None)
     }
 
-    #[cfg(test)]
     pub fn copy(id: impl Into<Arc<str>>) -> Self {
-        Self(Op::Copy, id.into())
+        Self(Op::Copy, id.into(), None)
     }
 
     pub fn write(id: impl Into<Arc<str>>) -> Self {
-        Self(Op::Write, id.into())
+        Self(Op::Write, id.into(), None)
+    }
+
+    pub fn fingerprint() -> Self {
+        Self(Op::Fingerprint, Arc::from("Fingerprint"), None)
+    }
+
+    pub fn site() -> Self {
+        Self(Op::Site, Arc::from("Site"), None)
+    }
+
+    pub fn manifest() -> Self {
+        Self(Op::Manifest, Arc::from("Manifest"), None)
     }
 
     pub fn finish() -> Self {
-        Self(Op::Finish, Arc::from("Finish"))
+        Self(Op::Finish, Arc::from("Finish"), None)
     }
 
     pub fn graph(id: impl Into<Arc<str>>) -> Self {
-        Self(Op::Graph, id.into())
+        Self(Op::Graph, id.into(), None)
+    }
+
+    pub fn op(&self) -> Op {
+        self.0
     }
 
     pub fn uri(&self) -> URI {
+        qualify(&self.unqualified_uri(), self.2.as_deref())
+    }
+
+    fn unqualified_uri(&self) -> URI {
         match self.0 {
             Op::Gather => String::from("gather:"),
             Op::Exec => format!("exec:{}", self.1),
@@ -187,6 +288,10 @@ impl OpId {
             Op::Compile => format!("compile:{}", self.1),
             Op::Write => format!("write:{}", self.1),
             Op::Copy => format!("copy:{}", self.1),
+            Op::Theme => format!("theme:{}", self.1),
+            Op::Fingerprint => String::from("fingerprint:"),
+            Op::Site => String::from("site:"),
+            Op::Manifest => String::from("manifest:"),
             Op::Graph => format!("graph:{}", self.1),
             Op::Finish => String::from("finish:"),
         }
@@ -194,7 +299,7 @@ impl OpId {
 
     pub fn uri_path(&self) -> String {
         match self.0 {
-            Op::Finish | Op::Gather => String::new(),
+            Op::Finish | Op::Gather | Op::Fingerprint | Op::Site | Op::Manifest => String::new(),
             _ => self.1.to_string(),
         }
     }
@@ -223,11 +328,15 @@ impl From<&Operation> for OpId {
             | Tangle { id, .. }
             | Exec { id, .. }
             | Parse { id, .. }
-            | Preprocess { id, .. }
-            | Compile { id, .. }
             | CompilePlaintext { id, .. }
-            | Write { id }
-            | Copy { id, .. } => OpId(other.into(), id.clone()),
+            | Copy { id, .. }
+            | Theme { name: id } => OpId(other.into(), id.clone(), None),
+            Preprocess { id, format, .. } | Compile { id, format } | Write { id, format } => {
+                OpId(other.into(), id.clone(), format.clone())
+            }
+            Fingerprint => OpId::fingerprint(),
+            Site { .. } => OpId::site(),
+            Manifest { .. } => OpId::manifest(),
             Graph { graph_type } => OpId::graph(graph_type.to_string()),
             Finish => OpId::finish(),
         }
@@ -239,14 +348,20 @@ impl FromStr for OpId {
 
     fn from_str(other: &str) -> Result<Self, Self::Err> {
         let (schema, path) = other.split_once(':').ok_or(AppError::bad_uri(other))?;
+        let (schema, format) = match schema.split_once('+') {
+            Some((schema, format)) => (schema, Some(Arc::from(format))),
+            None => (schema, None),
+        };
         let op = match schema {
             "file" => Op::Load,
             "ast" => Op::Parse,
             "parse" => Op::Preprocess,
             "exec" => Op::Exec,
             "copy" => Op::Copy,
+            "write" => Op::Write,
+            "theme" => Op::Theme,
             _ => return Err(AppError::unknown_schema(schema)),
         };
-        Ok(Self(op, Arc::from(path)))
+        Ok(Self(op, Arc::from(path), format))
     }
 }
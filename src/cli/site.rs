@@ -0,0 +1,156 @@
+use std::fmt::Write;
+
+use htmlize::{escape_attribute, escape_text};
+
+/// Written document to be published
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SiteEntry {
+    pub path: String,
+    pub title: Option<String>,
+    pub date: Option<String>,
+    pub summary: Option<String>,
+}
+
+/// Build sitemap of all entries
+pub fn sitemap(base_url: &str, entries: &[SiteEntry]) -> String {
+    let base_url = base_url.trim_end_matches('/');
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    out.push_str("<urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n");
+    for entry in entries {
+        let loc = escape_text(format!("{base_url}/{}", entry.path));
+        writeln!(&mut out, "  <url>\n    <loc>{loc}</loc>").expect("write");
+        if let Some(date) = &entry.date {
+            writeln!(&mut out, "    <lastmod>{}</lastmod>", escape_text(date)).expect("write");
+        }
+        out.push_str("  </url>\n");
+    }
+    out.push_str("</urlset>\n");
+    out
+}
+
+/// Build Atom feed of entries with a date, newest first
+pub fn feed(base_url: &str, title: &str, entries: &[SiteEntry]) -> String {
+    let base_url = base_url.trim_end_matches('/');
+    let mut dated = entries.iter().filter(|e| e.date.is_some()).collect::<Vec<_>>();
+    dated.sort_by(|a, b| b.date.cmp(&a.date).then_with(|| a.path.cmp(&b.path)));
+    let updated = dated
+        .first()
+        .and_then(|e| e.date.as_deref())
+        .map(timestamp)
+        .unwrap_or_else(|| timestamp("1970-01-01"));
+
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+    out.push_str("<feed xmlns=\"http://www.w3.org/2005/Atom\">\n");
+    writeln!(&mut out, "  <title>{}</title>", escape_text(title)).expect("write");
+    writeln!(&mut out, "  <id>{}/</id>", escape_text(base_url)).expect("write");
+    writeln!(&mut out, "  <link href=\"{}/\"/>", escape_attribute(base_url)).expect("write");
+    writeln!(&mut out, "  <updated>{updated}</updated>").expect("write");
+    for entry in dated {
+        let url = format!("{base_url}/{}", entry.path);
+        let title = entry.title.as_deref().unwrap_or(&entry.path);
+        let date = timestamp(entry.date.as_deref().expect("dated"));
+        out.push_str("  <entry>\n");
+        writeln!(&mut out, "    <title>{}</title>", escape_text(title)).expect("write");
+        writeln!(&mut out, "    <link href=\"{}\"/>", escape_attribute(&url)).expect("write");
+        writeln!(&mut out, "    <id>{}</id>", escape_text(&url)).expect("write");
+        writeln!(&mut out, "    <updated>{date}</updated>").expect("write");
+        if let Some(summary) = &entry.summary {
+            writeln!(&mut out, "    <summary>{}</summary>", escape_text(summary)).expect("write");
+        }
+        out.push_str("  </entry>\n");
+    }
+    out.push_str("</feed>\n");
+    out
+}
+
+/// Expand plain dates to RFC 3339 timestamps
+fn timestamp(date: &str) -> String {
+    match date.len() {
+        10 => format!("{date}T00:00:00Z"),
+        _ => date.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn entries() -> Vec<SiteEntry> {
+        vec![
+            SiteEntry {
+                path: "blog/index.html".to_string(),
+                title: Some("Blog".to_string()),
+                ..Default::default()
+            },
+            SiteEntry {
+                path: "blog/first.html".to_string(),
+                title: Some("First & foremost".to_string()),
+                date: Some("2024-01-01".to_string()),
+                summary: Some("The first post".to_string()),
+            },
+            SiteEntry {
+                path: "blog/second.html".to_string(),
+                title: None,
+                date: Some("2024-02-01".to_string()),
+                summary: None,
+            },
+        ]
+    }
+
+    #[test]
+    fn test_sitemap() {
+        let result = sitemap("https://example.com/", &entries());
+        assert_eq!(
+            result,
+            indoc! {r#"
+            <?xml version="1.0" encoding="UTF-8"?>
+            <urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+              <url>
+                <loc>https://example.com/blog/index.html</loc>
+              </url>
+              <url>
+                <loc>https://example.com/blog/first.html</loc>
+                <lastmod>2024-01-01</lastmod>
+              </url>
+              <url>
+                <loc>https://example.com/blog/second.html</loc>
+                <lastmod>2024-02-01</lastmod>
+              </url>
+            </urlset>
+            "#}
+        );
+    }
+
+    #[test]
+    fn test_feed() {
+        let result = feed("https://example.com", "My blog", &entries());
+        assert_eq!(
+            result,
+            indoc! {r#"
+            <?xml version="1.0" encoding="utf-8"?>
+            <feed xmlns="http://www.w3.org/2005/Atom">
+              <title>My blog</title>
+              <id>https://example.com/</id>
+              <link href="https://example.com/"/>
+              <updated>2024-02-01T00:00:00Z</updated>
+              <entry>
+                <title>blog/second.html</title>
+                <link href="https://example.com/blog/second.html"/>
+                <id>https://example.com/blog/second.html</id>
+                <updated>2024-02-01T00:00:00Z</updated>
+              </entry>
+              <entry>
+                <title>First &amp; foremost</title>
+                <link href="https://example.com/blog/first.html"/>
+                <id>https://example.com/blog/first.html</id>
+                <updated>2024-01-01T00:00:00Z</updated>
+                <summary>The first post</summary>
+              </entry>
+            </feed>
+            "#}
+        );
+    }
+}
@@ -1,19 +1,27 @@
+use std::collections::HashSet;
 use std::sync::atomic::Ordering;
 use std::sync::{Arc, MutexGuard};
+use std::time::Instant;
 
 use futures::stream::FuturesUnordered;
-use futures::{future::BoxFuture, FutureExt};
-use log::{error, info};
+use futures::future::{self, BoxFuture};
+use futures::FutureExt;
+use log::{debug, error, info, warn};
 use murkdown::types::{ExecArtifact, ExecInput, LocationMap};
 use tokio::task::yield_now;
 use tokio_stream::StreamExt;
 
-use super::command::Command;
+use super::artifact::Artifact;
+use super::command::{Command, LangCommand};
 use super::graph_sorter::grouped_topological_sort;
-use super::op::{OpId, Operation};
+use super::lang;
+use super::logger;
+use super::op::{Op, OpId, Operation};
 use super::state_context::State;
 use super::task;
-use super::types::Source;
+use super::theme;
+use super::timings::{self, Counts, Timing};
+use super::types::{AssetMode, Output, Source};
 use super::utils::parents;
 use super::{
     command::Config,
@@ -22,7 +30,7 @@ use super::{
 
 pub async fn handle(event_rx: EventRx, config: &Config) -> Result<(), AppError> {
     let state = State::new();
-    state.load_languages(config.format.as_ref().expect("format"))?;
+    state.load_languages(&config.formats(), config.theme.as_deref())?;
 
     handle_state(event_rx, config, state).await
 }
@@ -50,12 +58,51 @@ pub async fn handle_state(
             process_graph(config, &mut tasks, &state);
 
             if done(&tasks, &state) {
+                if config.timings || config.timings_report.is_some() {
+                    report_timings(config, &state).await?;
+                }
                 break Ok(());
             }
         }
     }
 }
 
+async fn report_timings(config: &Config, state: &State) -> Result<(), AppError> {
+    let timings = {
+        let timings = state.timings.lock().expect("poisoned lock");
+        let artifacts = state.artifacts.lock().expect("poisoned lock");
+        timings
+            .iter()
+            .map(|(id, duration)| Timing {
+                id: id.clone(),
+                duration: *duration,
+                size: artifacts.get(&id.uri()).and_then(Artifact::size),
+            })
+            .collect::<Vec<_>>()
+    };
+
+    if config.timings {
+        for line in timings::summary(&timings) {
+            info!("{line}");
+        }
+    }
+
+    if let Some(path) = &config.timings_report {
+        let count = |op| timings.iter().filter(|t| t.id.op() == op).count();
+        let counts = Counts {
+            documents: count(Op::Write),
+            assets: count(Op::Copy),
+            warnings: logger::WARNINGS.load(Ordering::Relaxed),
+        };
+        debug!("Writing timings report to {}", path.display());
+        tokio::fs::write(path, timings::report(&timings, counts))
+            .await
+            .map_err(|err| AppError::write_error(err, path.clone()))?;
+    }
+
+    Ok(())
+}
+
 fn process_event(
     event: Event,
     config: &Config,
@@ -97,7 +144,7 @@ fn process_event(
 
                 // NOTE: tasks are scheduled here because there should only be one graph task
                 state.insert_op_chain([
-                    Operation::Gather { cmd, sources, splits },
+                    Operation::Gather { cmd, sources, splits, formats: vec![None] },
                     Operation::Finish,
                     Operation::Graph { graph_type },
                     Operation::Exec {
@@ -106,7 +153,7 @@ fn process_event(
                         input,
                         artifact: ExecArtifact::Stdout("image/png".to_string()),
                     },
-                    Operation::Write { id },
+                    Operation::Write { id, format: None },
                 ]);
             }
             Command::Ping => {
@@ -120,17 +167,69 @@ fn process_event(
                     locs.insert(source.path()?, source.into());
                 }
             }
-            Command::Build { ref paths, ref splits, .. } => {
-                info!(target = "status"; "Building {} sources to {}", paths.len(), config.output.as_ref().unwrap());
+            Command::Build { ref paths, ref splits, dry_run, .. } => {
+                let verb = if dry_run { "Planning" } else { "Building" };
+                info!(target = "status"; "{verb} {} sources to {}", paths.len(), config.output.as_ref().unwrap());
+                state.dry_run.store(dry_run, Ordering::Relaxed);
                 let (sources, parents) = {
                     let locs = state.locations.lock().expect("poisoned lock");
                     get_sources_and_parents(paths, locs)?
                 };
                 let splits = Some(splits.clone());
 
+                // NOTE: operations of the primary format are not qualified by format
+                let formats = config
+                    .formats()
+                    .into_iter()
+                    .enumerate()
+                    .map(|(idx, format)| (idx > 0).then(|| Arc::from(format)))
+                    .collect();
+
                 tasks.push(task::index(parents, state.locations.clone()).boxed());
                 state.insert_op_chain([
-                    Operation::Gather { cmd, sources, splits },
+                    Operation::Gather { cmd, sources: sources.clone(), splits, formats },
+                    Operation::Finish,
+                ]);
+
+                let assets = config.asset_mode();
+                if let Some(dir) = config.theme.as_deref().filter(|_| assets != AssetMode::Inline) {
+                    let name = Arc::from(theme::name(dir)?);
+                    state.insert_op_chain([Operation::Theme { name }, Operation::Finish]);
+                }
+
+                // NOTE: operations after finish run in sequence, so that the last one is done last
+                let mut chain = vec![Operation::Finish];
+
+                // NOTE: asset manifest is scheduled after finish, so that all assets are copied
+                if assets == AssetMode::Fingerprint {
+                    chain.push(Operation::Fingerprint);
+                }
+
+                // NOTE: site is scheduled after finish, so that all documents are written
+                if let Some(base_url) = &config.base_url {
+                    let title = config.feed_title.clone().unwrap_or(base_url.clone());
+                    chain.push(Operation::Site { base_url: base_url.clone(), title });
+                }
+
+                // NOTE: build manifest is scheduled last, so that all outputs are recorded
+                if config.tracks_outputs() {
+                    chain.push(Operation::Manifest { clean: config.clean, sources });
+                } else if config.clean {
+                    let output = config.output.as_ref().unwrap();
+                    warn!("Cleaning skipped since outputs to {output} are not tracked");
+                }
+                state.insert_op_chain(chain);
+            }
+            Command::Tangle { ref paths } => {
+                info!(target = "status"; "Tangling {} sources to {}", paths.len(), config.output.as_ref().unwrap());
+                let (sources, parents) = {
+                    let locs = state.locations.lock().expect("poisoned lock");
+                    get_sources_and_parents(paths, locs)?
+                };
+
+                tasks.push(task::index(parents, state.locations.clone()).boxed());
+                state.insert_op_chain([
+                    Operation::Gather { cmd, sources, splits: None, formats: vec![None] },
                     Operation::Finish,
                 ]);
             }
@@ -139,6 +238,43 @@ fn process_event(
                 state.clear();
                 tasks.clear();
             }
+            Command::Lang { command: LangCommand::List } => {
+                for entry in lang::available() {
+                    let source = match &entry.path {
+                        Some(path) => path.display().to_string(),
+                        None => String::from("builtin"),
+                    };
+                    let lang = match &entry.lang {
+                        Ok(lang) => lang,
+                        Err(err) => {
+                            warn!("{:<12} {:<16} {err}", entry.name, "?");
+                            continue;
+                        }
+                    };
+                    let source = match &lang.extends {
+                        Some(parent) => format!("{source}, extends {parent}"),
+                        None => source,
+                    };
+                    info!("{:<12} {:<16} {source}", entry.name, lang.media_type);
+                }
+            }
+            Command::Lang { command: LangCommand::Show { ref name } } => {
+                let source = lang::source(name)?;
+                match config.output {
+                    Some(Output::StdOutLog) => info!(target = name.as_str(); "{source}"),
+                    _ => print!("{source}"),
+                }
+            }
+            Command::Lang { command: LangCommand::Check { ref names } } => {
+                let problems = lang::check(names)?;
+                if problems > 0 {
+                    return Err(AppError::lang_check_failed(problems));
+                }
+            }
+            Command::Lang { command: LangCommand::Eject { ref name, ref path } } => {
+                let path = lang::eject(name, path.as_deref())?;
+                info!(target = "status"; "Ejected {name} lang to {}", path.display());
+            }
             Command::Exit => state.should_exit.store(true, Ordering::Relaxed),
         },
         Event::Command(Err(e)) => {
@@ -182,6 +318,12 @@ fn process_graph(
     let operations = state.operations.lock().expect("poisoned lock");
     let sorted = grouped_topological_sort(&*operations).unwrap();
 
+    // NOTE: a command is done once an operation that nothing depends on is done
+    let dependencies = operations
+        .iter()
+        .flat_map(|(_, _, deps)| deps)
+        .collect::<HashSet<_>>();
+
     let next_tasks = sorted
         .into_iter()
         .skip_while(|group| group.iter().all(|id| state.is_op_processed(id)));
@@ -199,30 +341,70 @@ fn process_graph(
             let ops = state.operations.clone();
             let locs = state.locations.clone();
             let langs = state.languages.clone();
+            let themes = state.themes.clone();
             let out = config.output.clone().expect("output");
-            let fmt = config.format.clone().expect("format");
+            let fmt = op.format().map_or(config.primary_format(), |f| f).to_string();
+            let assets = config.asset_mode();
+
+            let last = !dependencies.contains(&opid);
+
+            // NOTE: dry runs expand the graph but only describe operations with side effects
+            if state.dry_run.load(Ordering::Relaxed) && op.is_planned() {
+                if let Some(plan) = task::plan(&op, &fmt, &langs, assets, &out, &operations) {
+                    for line in plan.lines() {
+                        info!("{line}");
+                    }
+                }
+                if last {
+                    tasks.push(future::ok(true).boxed());
+                }
+                state.mark_op_processed(opid.clone());
+                continue;
+            }
 
             use Operation::*;
-            match vertex {
-                Gather { .. } => tasks.push(task::gather(op, ops).boxed()),
-                Exec { .. } => tasks.push(task::exec(op, asts, arts).boxed()),
-                Load { .. } => tasks.push(task::load(op, asts, arts).boxed()),
-                Tangle { .. } => tasks.push(task::tangle(op, dep.unwrap(), arts).boxed()),
-                Parse { .. } => tasks.push(task::parse(op, dep.unwrap(), arts).boxed()),
-                Preprocess { .. } => tasks.push(
-                    task::preprocess(op, fmt, dep.unwrap(), asts, ops, arts, langs, locs).boxed(),
-                ),
+            let task = match vertex {
+                Gather { .. } => task::gather(op, ops).boxed(),
+                Exec { .. } => task::exec(op, asts, arts).boxed(),
+                Load { .. } => task::load(op, asts, arts).boxed(),
+                Tangle { .. } => task::tangle(op, dep.unwrap(), arts, langs, out).boxed(),
+                Parse { .. } => {
+                    task::parse(op, fmt, dep.unwrap(), asts, ops, arts, langs, locs).boxed()
+                }
+                Preprocess { .. } => {
+                    task::preprocess(op, fmt, dep.unwrap(), asts, ops, arts, langs, locs, assets)
+                        .boxed()
+                }
                 Compile { .. } => {
-                    tasks.push(task::compile(op, fmt, dep.unwrap(), arts, langs).boxed())
+                    task::compile(op, fmt, dep.unwrap(), arts, langs, themes, locs, assets)
+                        .boxed()
                 }
                 CompilePlaintext { source_uri, .. } => {
-                    tasks.push(task::compile_plaintext(op, source_uri.clone(), arts, langs).boxed())
+                    task::compile_plaintext(op, source_uri.clone(), arts, langs).boxed()
                 }
-                Write { .. } => tasks.push(task::write(op, dep.unwrap(), arts, out).boxed()),
-                Copy { .. } => tasks.push(task::copy(op, out).boxed()),
-                Graph { .. } => tasks.push(task::graph(op, ops, arts).boxed()),
-                Finish => tasks.push(task::finish(op).boxed()),
-            }
+                Write { .. } => task::write(op, dep.unwrap(), arts, out).boxed(),
+                Copy { .. } => task::copy(op, assets, arts, out).boxed(),
+                Fingerprint => task::fingerprint(arts, out).boxed(),
+                Theme { .. } => task::theme(op, themes, arts, out).boxed(),
+                Site { .. } => task::site(op, asts, arts, out).boxed(),
+                Manifest { .. } => task::manifest(op, arts, out).boxed(),
+                Graph { .. } => task::graph(op, ops, arts).boxed(),
+                Finish => task::finish(op).boxed(),
+            };
+
+            // NOTE: timer starts on first poll, so that queued tasks are not penalized
+            let id = opid.clone();
+            let timings = state.timings.clone();
+            tasks.push(
+                async move {
+                    let started = Instant::now();
+                    let result = task.await;
+                    let mut timings = timings.lock().expect("poisoned lock");
+                    timings.push((id, started.elapsed()));
+                    result.map(|done| done || last)
+                }
+                .boxed(),
+            );
             state.mark_op_processed(opid.clone());
         }
 
@@ -1,7 +1,8 @@
 use std::{
     collections::HashSet,
-    path::PathBuf,
+    path::Path,
     sync::{atomic::AtomicBool, Arc, Mutex, OnceLock},
+    time::Duration,
 };
 
 use hashbrown::HashMap;
@@ -12,8 +13,10 @@ use murkdown::types::{AstMap, LocationMap};
 use super::artifact::Artifact;
 use super::{
     graph::OpGraph,
+    lang,
     op::{OpId, Operation},
-    types::{AppError, AppErrorPathCtx, ArtifactMap, LangMap},
+    theme,
+    types::{AppError, ArtifactMap, LangMap, ThemeMap},
 };
 
 /// State container
@@ -23,9 +26,12 @@ pub(crate) struct State {
     pub asts: Arc<Mutex<AstMap>>,
     pub locations: Arc<Mutex<LocationMap>>,
     pub languages: Arc<OnceLock<LangMap>>,
+    pub themes: Arc<OnceLock<ThemeMap>>,
     pub operations: Arc<Mutex<OpGraph>>,
     pub operations_processed: Arc<Mutex<HashSet<OpId>>>,
     pub should_exit: Arc<AtomicBool>,
+    pub dry_run: Arc<AtomicBool>,
+    pub timings: Arc<Mutex<Vec<(OpId, Duration)>>>,
 }
 
 impl State {
@@ -35,16 +41,19 @@ impl State {
             asts: Arc::new(Mutex::new(HashMap::new())),
             locations: Arc::new(Mutex::new(HashMap::new())),
             languages: Arc::new(OnceLock::new()),
+            themes: Arc::new(OnceLock::new()),
             operations: Arc::new(Mutex::new(OpGraph::new())),
             operations_processed: Arc::new(Mutex::new(HashSet::new())),
             should_exit: Arc::new(AtomicBool::new(false)),
+            dry_run: Arc::new(AtomicBool::new(false)),
+            timings: Arc::new(Mutex::new(Vec::new())),
         }
     }
 
     #[cfg(test)]
     pub fn new_loaded(format: &str) -> Self {
         let ctx = Self::new();
-        ctx.load_languages(format).expect("valid format");
+        ctx.load_languages(&[format], None).expect("valid format");
         ctx
     }
 
@@ -84,24 +93,31 @@ impl State {
         processed.contains(id)
     }
 
-    pub fn load_languages(&self, format: &str) -> Result<(), AppError> {
+    pub fn load_languages(&self, formats: &[&str], theme: Option<&Path>) -> Result<(), AppError> {
+        if self.themes.get().is_none() {
+            self.themes.set(theme::load(theme)?).expect("themes are loaded");
+        }
+
         if self.languages.get().is_none() {
             // builtin
-            let markdown = include_str!("../lib/compiler/markdown.lang");
-            let html = include_str!("../lib/compiler/html.lang");
-            let plaintext = include_str!("../lib/compiler/plaintext.lang");
-
-            let mut languages = HashMap::from([
-                ("markdown".to_string(), Lang::new(markdown)?),
-                ("html".to_string(), Lang::new(html)?),
-                ("plaintext".to_string(), Lang::new(plaintext)?),
-            ]);
+            let mut languages = lang::builtins()?;
 
             // custom
-            if !languages.contains_key(format) {
-                let path = PathBuf::from(format).with_extension("lang");
-                let custom = std::fs::read_to_string(&path).with_ctx(path)?;
-                languages.insert(format.to_string(), Lang::new(&custom)?);
+            for format in formats {
+                lang::load(&mut languages, format, &mut Vec::new())?;
+            }
+
+            // theme overlays
+            // NOTE: overlays only apply to formats producing the same media type
+            let themes = self.themes.get().expect("themes are loaded");
+            for format in formats {
+                let lang = languages.get_mut(*format).expect("language is loaded");
+                for overlay in themes.values().filter_map(|t| t.lang.as_deref()) {
+                    let overlay = Lang::new(overlay)?;
+                    if overlay.media_type == lang.media_type {
+                        lang.overlay(overlay);
+                    }
+                }
             }
 
             self.languages.set(languages).expect("languages are loaded");
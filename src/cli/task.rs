@@ -1,7 +1,7 @@
 use std::{
     fmt::Write,
     os::unix::fs::PermissionsExt,
-    path::{Path, PathBuf},
+    path::{Component, Path, PathBuf},
     sync::{Arc, Mutex, OnceLock},
 };
 
@@ -11,23 +11,28 @@ use hashbrown::hash_map::Entry;
 use log::{debug, info, trace, warn};
 use mime2ext::mime2ext;
 use murkdown::{
-    ast::{Node, NodeBuilder},
-    types::{Dependency, ExecArtifact, ExecInput, LibErrorPathCtx, LocationMap, URI},
+    ast::{Detached, Node, NodeBuilder},
+    types::{
+        Dependency, ExecArtifact, ExecInput, LibErrorPathCtx, Location, LocationMap, URI,
+    },
 };
-use murkdown::{compiler, parser};
+use murkdown::{compiler, data, parser, source};
 use murkdown::{preprocessor, types::AstMap};
 use tokio::fs;
 use walkdir::{DirEntry, WalkDir};
 
 use super::{
     graph::OpGraph,
-    op::{OpId, Operation},
-    types::{AppError, AppErrorPathCtx, ArtifactMap, LangMap, Output},
+    op::{self, OpId, Operation},
+    types::{AppError, AppErrorPathCtx, ArtifactMap, AssetMode, LangMap, Output, ThemeMap},
     utils::{is_file, is_visible},
 };
 use crate::cli::{
     artifact::Artifact,
     command::{Command, GraphType},
+    fingerprint, inline,
+    manifest::{self, ManifestEntry},
+    site::{self, SiteEntry},
     types::Source,
     utils::{
         into_id_source_tuple, into_uri_path_tuple, is_sensible, spawn_command, wait_command,
@@ -35,6 +40,9 @@ use crate::cli::{
     },
 };
 
+/// Schemes of artifacts that are recorded in the build manifest
+static OUTPUT_SCHEMES: &[&str] = &["write", "copy", "theme", "site", "fingerprint"];
+
 /// Index the contents of provided paths
 pub async fn index(
     paths: Vec<PathBuf>,
@@ -64,7 +72,7 @@ pub async fn index(
 
 /// Gather entry points and schedule dependencies
 pub async fn gather(op: Operation, operations: Arc<Mutex<OpGraph>>) -> Result<bool, AppError> {
-    let Operation::Gather { ref cmd, ref sources, .. } = op else {
+    let Operation::Gather { ref cmd, ref sources, ref formats, .. } = op else {
         panic!()
     };
     debug!("Gathering files");
@@ -77,11 +85,13 @@ pub async fn gather(op: Operation, operations: Arc<Mutex<OpGraph>>) -> Result<bo
         path_is_md || sources.iter().any(|p| p == path)
     };
 
+    let mut prev_tangle = None;
     for source in sources {
         // build iterator over source paths
         let walker = match source {
             Source::Path(path) => {
                 let items_from_path = WalkDir::new(path)
+                    .sort_by_file_name()
                     .into_iter()
                     .filter_entry(is_visible)
                     .filter_map(Result::ok)
@@ -108,16 +118,44 @@ pub async fn gather(op: Operation, operations: Arc<Mutex<OpGraph>>) -> Result<bo
 
             count += 1;
             match cmd {
-                Command::Build { headers, .. } | Command::Graph { headers, .. } => graph
-                    .insert_node_chain([
+                Command::Build { headers, .. } | Command::Graph { headers, .. } => {
+                    graph.insert_node_chain([
                         op.clone(),
                         Operation::Load { id: id.clone(), source },
                         Operation::Parse { id: id.clone() },
-                        Operation::Preprocess { id: id.clone(), headers: headers.clone() },
-                        Operation::Compile { id: id.clone() },
-                        Operation::Write { id: id.clone() },
+                    ]);
+
+                    // NOTE: loaded and parsed documents are shared by all formats
+                    for format in formats {
+                        graph.insert_node_chain([
+                            Operation::Parse { id: id.clone() },
+                            Operation::Preprocess {
+                                id: id.clone(),
+                                headers: headers.clone(),
+                                format: format.clone(),
+                            },
+                            Operation::Compile { id: id.clone(), format: format.clone() },
+                            Operation::Write { id: id.clone(), format: format.clone() },
+                            Operation::Finish,
+                        ]);
+                    }
+                }
+                Command::Tangle { .. } => {
+                    graph.insert_node_chain([
+                        op.clone(),
+                        Operation::Load { id: id.clone(), source },
+                        Operation::Parse { id: id.clone() },
+                        Operation::Preprocess { id: id.clone(), headers: None, format: None },
+                        Operation::Tangle { id: id.clone(), target: None },
                         Operation::Finish,
-                    ]),
+                    ]);
+
+                    // NOTE: tangle documents in order, so shared targets are concatenated in order
+                    let next = OpId::tangle(id.clone());
+                    if let Some(prev) = prev_tangle.replace(next.clone()) {
+                        graph.add_dependency(next, prev);
+                    }
+                }
                 _ => panic!("gather on bad command"),
             }
         }
@@ -313,15 +351,21 @@ pub async fn load(
     Ok(false)
 }
 
-/// Tangle file
+/// Tangle file, or blocks of a document
 pub async fn tangle(
     op: Operation,
     dep: URI,
     artifacts: Arc<Mutex<ArtifactMap>>,
+    languages: Arc<OnceLock<LangMap>>,
+    output: Output,
 ) -> Result<bool, AppError> {
     let Operation::Tangle { id, target } = &op else {
         unreachable!()
     };
+
+    let Some(target) = target else {
+        return tangle_document(id, dep, artifacts, languages, output).await;
+    };
     debug!("Tangling {id} to {}", target.display());
 
     let input = {
@@ -350,12 +394,89 @@ pub async fn tangle(
     Ok(false)
 }
 
-/// Parse files to AST
+/// Tangle blocks of a document to output
+async fn tangle_document(
+    id: &str,
+    dep: URI,
+    artifacts: Arc<Mutex<ArtifactMap>>,
+    languages: Arc<OnceLock<LangMap>>,
+    output: Output,
+) -> Result<bool, AppError> {
+    debug!("Tangling {id} from {dep}");
+    let blocks = {
+        let artifacts = artifacts.lock().expect("poisoned lock");
+        let ast = artifacts.get(&dep).expect("no tangle dependency");
+        let lang = languages
+            .get()
+            .expect("languages not loaded")
+            .get("plaintext")
+            .expect("plaintext to be defined");
+
+        let (mut node, _detached) = detach_loaded(ast);
+        compiler::tangle(&mut node, lang)?
+    };
+
+    for (path, content) in blocks {
+        let root = match &output {
+            Output::StdOut => {
+                debug!("Tangling {id} to stdout");
+                print!("{content}");
+                continue;
+            }
+            Output::StdOutLog => {
+                debug!("Tangling {id} to stdout");
+                info!(target = &*path; "{}", content);
+                continue;
+            }
+            Output::Path(root) => root,
+        };
+
+        let relative = Path::new(&*path);
+        if relative.is_absolute() || relative.components().any(|c| c.as_os_str() == "..") {
+            return Err(AppError::bad_path(relative));
+        }
+        let target = root.join(relative);
+        debug!("Tangling {id} to {}", target.display());
+
+        // NOTE: targets tangled earlier in this build are appended to
+        let uri = format!("tangle:{}", target.display());
+        let content = {
+            let mut artifacts = artifacts.lock().expect("poisoned lock");
+            let content = match artifacts.get(&uri) {
+                Some(Artifact::Plaintext(_, previous)) => format!("{previous}{content}"),
+                _ => content,
+            };
+            let artifact = Artifact::Plaintext("text/plain".to_string(), content.clone());
+            artifacts.insert(uri, artifact);
+            content
+        };
+
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)
+                .await
+                .map_err(|err| AppError::write_error(err, parent))?;
+        }
+        fs::write(&target, content)
+            .await
+            .map_err(|err| AppError::write_error(err, target))?;
+    }
+
+    Ok(false)
+}
+
+/// Parse files to AST and schedule documents it indexes
+#[allow(clippy::too_many_arguments)]
 pub async fn parse(
     op: Operation,
+    format: String,
     dep: URI,
+    asts: Arc<Mutex<AstMap>>,
+    operations: Arc<Mutex<OpGraph>>,
     artifacts: Arc<Mutex<ArtifactMap>>,
+    languages: Arc<OnceLock<LangMap>>,
+    locations: Arc<Mutex<LocationMap>>,
 ) -> Result<bool, AppError> {
+    let uri = op.uri();
     let Operation::Parse { id } = &op else {
         unreachable!()
     };
@@ -365,8 +486,38 @@ pub async fn parse(
 
     match content {
         Artifact::Plaintext(_, content) => {
-            let ast = parser::parse(content).with_path(id)?;
-            artifacts.insert(op.uri(), Artifact::Ast(ast));
+            let ast = match id {
+                _ if data::is_data(id) => data::parse(content, id)?,
+                _ if source::is_source(id) => source::parse(content),
+                _ => source::attach_raw(parser::parse(content).with_path(id)?, content),
+            };
+
+            // NOTE: indexed documents are parsed before preprocessing, which reads their props
+            let locs = locations.lock().expect("poisoned lock");
+            let lang = languages
+                .get()
+                .expect("languages not loaded")
+                .get(&format)
+                .ok_or(AppError::unknown_language(format))?;
+            let targets = preprocessor::index_targets(&ast, &locs, id, lang);
+            if !targets.is_empty() {
+                let mut graph = operations.lock().expect("poisoned lock");
+                schedule_index_targets(id, targets, &mut graph, &locs)?;
+            }
+
+            // upsert node to ast
+            let mut asts = asts.lock().expect("poisoned lock");
+            match asts.entry(uri.clone()) {
+                Entry::Occupied(r) => {
+                    let mut mutex = r.get().lock().expect("poisoned lock");
+                    *mutex = ast.clone();
+                }
+                Entry::Vacant(r) => {
+                    r.insert(Arc::new(Mutex::new(ast.clone())));
+                }
+            };
+
+            artifacts.insert(uri, Artifact::Ast(ast));
         }
         _ => todo!(),
     }
@@ -374,6 +525,40 @@ pub async fn parse(
     Ok(false)
 }
 
+/// Make preprocessing of a document wait for parsing of the documents it indexes
+fn schedule_index_targets(
+    id: &str,
+    targets: Vec<String>,
+    graph: &mut OpGraph,
+    locs: &LocationMap,
+) -> Result<(), AppError> {
+    let preprocesses = graph
+        .iter()
+        .filter(|(_, op, _)| matches!(op, Operation::Preprocess { id: i, .. } if &**i == id))
+        .map(|(opid, _, _)| opid.clone())
+        .collect::<Vec<_>>();
+
+    for target in targets {
+        let target: Arc<str> = Arc::from(target);
+        if graph.get(&OpId::parse(target.clone())).is_none() {
+            let source = locs
+                .get(&*target)
+                .ok_or(AppError::file_not_found(&*target))?
+                .clone()
+                .into();
+            trace!("Schedule parse:{target} for index of {id}");
+            graph.insert_node_chain([
+                Operation::Load { id: target.clone(), source },
+                Operation::Parse { id: target.clone() },
+            ]);
+        }
+        for preprocess in &preprocesses {
+            graph.add_dependency(preprocess.clone(), OpId::parse(target.clone()));
+        }
+    }
+    Ok(())
+}
+
 /// Preprocess AST and schedule dependencies
 #[allow(clippy::too_many_arguments)]
 pub async fn preprocess(
@@ -385,8 +570,9 @@ pub async fn preprocess(
     artifacts: Arc<Mutex<ArtifactMap>>,
     languages: Arc<OnceLock<LangMap>>,
     locations: Arc<Mutex<LocationMap>>,
+    assets: AssetMode,
 ) -> Result<bool, AppError> {
-    let Operation::Preprocess { ref id, ref headers } = op else {
+    let Operation::Preprocess { ref id, ref headers, format: ref qualifier } = op else {
         unreachable!()
     };
     debug!("Preprocessing {id}");
@@ -407,8 +593,18 @@ pub async fn preprocess(
                 .get(&format)
                 .ok_or(AppError::unknown_language(format))?;
             let headers = headers.as_deref();
-            let (deps, new_asts) =
-                preprocessor::preprocess(&mut node, headers, &mut asts, &locs, id, lang)?;
+            let (deps, new_asts) = match qualifier.as_deref() {
+                None => preprocessor::preprocess(&mut node, headers, &mut asts, &locs, id, lang)?,
+                Some(qualifier) => {
+                    let mut view = format_view(&asts, qualifier);
+                    let result =
+                        preprocessor::preprocess(&mut node, headers, &mut view, &locs, id, lang)?;
+                    merge_view(&mut asts, view, qualifier);
+                    result
+                }
+            };
+            let qualifier = qualifier.as_deref();
+            let new_asts = new_asts.into_iter().map(|uri| op::qualify(&uri, qualifier));
 
             // upsert preprocessed node to ast
             let arc = match asts.entry(uri.to_string()) {
@@ -441,22 +637,21 @@ pub async fn preprocess(
                 let Dependency::URI(kind, ref uri) = uri else {
                     unreachable!()
                 };
+                let qualified = op::qualify(uri, qualifier);
                 // TODO: improve and clarify resolving
                 let (schema, uri_path) = uri.split_once(':').expect("uri to have schema");
                 let (uri_path_nofragment, fragment) =
                     uri_path.rsplit_once('#').unwrap_or((uri_path, ""));
                 let id: Arc<str> = Arc::from(uri_path_nofragment);
 
-                if graph
-                    .get_uri(&format!("{schema}:{uri_path_nofragment}"))
-                    .is_some()
-                {
-                    trace!("Skip {uri} since {schema}:{uri_path_nofragment} is already scheduled");
+                let nofragment = op::qualify(&format!("{schema}:{uri_path_nofragment}"), qualifier);
+                if graph.get_uri(&nofragment).is_some() {
+                    trace!("Skip {qualified} since {nofragment} is already scheduled");
                     continue;
                 }
 
-                if graph.get_uri(uri).is_some() {
-                    trace!("Skip {uri} since it is already scheduled");
+                if graph.get_uri(&qualified).is_some() {
+                    trace!("Skip {qualified} since it is already scheduled");
                     continue;
                 }
 
@@ -489,11 +684,12 @@ pub async fn preprocess(
                                 let target = parent.join(format!("{}.tmp", fragment));
                                 let cmd = target.display().to_string();
                                 let source_uri = format!("parse:{uri_path}");
+                                let source_uri = op::qualify(&source_uri, qualifier);
                                 trace!("Schedule exec:{id} using {}", target.display());
 
                                 graph.insert_node_chain([
                                     Operation::CompilePlaintext { id: id.clone(), source_uri },
-                                    Operation::Tangle { id: id.clone(), target },
+                                    Operation::Tangle { id: id.clone(), target: Some(target) },
                                     Operation::Exec { id, cmd, input: None, artifact },
                                     op.clone(),
                                 ]);
@@ -526,10 +722,11 @@ pub async fn preprocess(
                             }
                             "parse" => {
                                 trace!("Schedule preprocess:{id}");
+                                let format = qualifier.map(Arc::from);
                                 graph.insert_node_chain([
                                     Operation::Load { id: id.clone(), source },
                                     Operation::Parse { id: id.clone() },
-                                    Operation::Preprocess { id, headers: None },
+                                    Operation::Preprocess { id, headers: None, format },
                                     op.clone(),
                                 ]);
                             }
@@ -548,7 +745,20 @@ pub async fn preprocess(
                             let id: Arc<str> = uri_path.into();
                             trace!("Schedule write:{id}");
                             graph.add_dependency(OpId::write(id.clone()), OpId::exec(uri_path));
-                            graph.insert_node_chain([Operation::Write { id }, Operation::Finish]);
+                            graph.insert_node_chain([
+                                Operation::Write { id, format: None },
+                                Operation::Finish,
+                            ]);
+                        }
+                        "copy" | "theme" if assets == AssetMode::Inline => {
+                            trace!("Skip {schema}:{id} since assets are inlined");
+                        }
+                        "theme" => {
+                            trace!("Schedule theme:{id}");
+                            graph.insert_node_chain([
+                                Operation::Theme { name: id },
+                                Operation::Finish,
+                            ]);
                         }
                         "copy" => {
                             let source = locs
@@ -557,6 +767,17 @@ pub async fn preprocess(
                                 .clone()
                                 .into();
                             trace!("Schedule copy:{id}");
+                            // NOTE: compiles link fingerprinted paths from the copied assets
+                            if assets == AssetMode::Fingerprint {
+                                let compiles = graph
+                                    .iter()
+                                    .filter(|(_, op, _)| matches!(op, Operation::Compile { .. }))
+                                    .map(|(opid, _, _)| opid.clone())
+                                    .collect::<Vec<_>>();
+                                for compile in compiles {
+                                    graph.add_dependency(compile, OpId::copy(id.clone()));
+                                }
+                            }
                             graph.insert_node_chain([
                                 Operation::Copy { id, source },
                                 Operation::Finish,
@@ -570,12 +791,17 @@ pub async fn preprocess(
                                 .ok_or(AppError::file_not_found(uri_path))?
                                 .clone()
                                 .into();
+                            let format = qualifier.map(Arc::from);
                             graph.insert_node_chain([
                                 Operation::Load { id: id.clone(), source },
                                 Operation::Parse { id: id.clone() },
-                                Operation::Preprocess { id: id.clone(), headers: None },
-                                Operation::Compile { id: id.clone() },
-                                Operation::Write { id: id.clone() },
+                                Operation::Preprocess {
+                                    id: id.clone(),
+                                    headers: None,
+                                    format: format.clone(),
+                                },
+                                Operation::Compile { id: id.clone(), format: format.clone() },
+                                Operation::Write { id: id.clone(), format },
                                 Operation::Finish,
                             ]);
                         }
@@ -600,15 +826,40 @@ pub async fn preprocess(
     Ok(false)
 }
 
+/// Get view of ASTs with preprocessed ASTs of a secondary format in place of the primary ones
+fn format_view(asts: &AstMap, qualifier: &str) -> AstMap {
+    asts.iter()
+        .filter_map(|(uri, arc)| match uri.split_once(':') {
+            Some(("parse", _)) => None,
+            Some((scheme, path)) if scheme.starts_with("parse+") => {
+                (scheme == format!("parse+{qualifier}")).then(|| (format!("parse:{path}"), arc))
+            }
+            _ => Some((uri.clone(), arc)),
+        })
+        .map(|(uri, arc)| (uri, arc.clone()))
+        .collect()
+}
+
+/// Merge view of ASTs of a secondary format back, qualifying its preprocessed ASTs
+fn merge_view(asts: &mut AstMap, view: AstMap, qualifier: &str) {
+    for (uri, arc) in view {
+        asts.entry(op::qualify(&uri, Some(qualifier))).or_insert(arc);
+    }
+}
+
 /// Compile AST to string
+#[allow(clippy::too_many_arguments)]
 pub async fn compile(
     op: Operation,
     format: String,
     dep: URI,
     artifacts: Arc<Mutex<ArtifactMap>>,
     languages: Arc<OnceLock<LangMap>>,
+    themes: Arc<OnceLock<ThemeMap>>,
+    locations: Arc<Mutex<LocationMap>>,
+    assets: AssetMode,
 ) -> Result<bool, AppError> {
-    let Operation::Compile { ref id } = op else {
+    let Operation::Compile { ref id, format: ref subdir } = op else {
         unreachable!()
     };
     debug!("Compiling {id} from {dep}");
@@ -620,22 +871,194 @@ pub async fn compile(
         .get(&format)
         .ok_or(AppError::unknown_language(format))?;
     let media_type = lang.media_type.clone();
+    let locs = locations.lock().expect("poisoned lock");
 
-    let result = match ast.clone() {
-        Artifact::Ast(mut node) => compiler::compile(&mut node, lang).unwrap(),
-        Artifact::AstPointer(pointer) => {
-            let mutex = pointer.upgrade().unwrap();
-            let mut node = mutex.lock().unwrap();
-            compiler::compile(&mut node, lang).unwrap()
+    // NOTE: theme and other assets are copied once and linked relative to the build root,
+    // which secondary formats are written to a subdirectory of
+    let root = match id.matches('/').count() + usize::from(subdir.is_some()) {
+        0 => ".".to_string(),
+        depth => vec![".."; depth].join("/"),
+    };
+    let variables = [("root", root.as_str())];
+    let base = subdir.as_ref().map(|_| root.as_str());
+
+    // NOTE: only copied and executed assets are rewritten, written documents stay linked
+    let mut resolve_ref = |href: &str| match href.split_once(':').unwrap_or(("copy", href)) {
+        _ if !inline::is_local(href) => None,
+        (_, path) if Path::new(path).extension().is_some_and(|ext| ext == "md") => None,
+        (scheme @ ("copy" | "exec"), path) if assets == AssetMode::Inline => {
+            resolve_inline_ref(scheme, path, id, &locs, &artifacts).or_else(|| {
+                warn!("Inlining {href} skipped since it could not be resolved");
+                None
+            })
+        }
+        ("copy", path) if assets == AssetMode::Fingerprint => {
+            resolve_fingerprinted_ref(path, id, &locs, &artifacts, base)
         }
-        _ => panic!("compiling unknown artifact"),
+        (scheme @ ("copy" | "exec"), path) if subdir.is_some() => {
+            resolve_root_ref(scheme, path, id, &locs, &artifacts, &root)
+        }
+        _ => None,
     };
 
+    let load = |uri: &str| resolve_load(uri, id, &locs, &artifacts, themes.get());
+
+    let (mut node, _detached) = detach_loaded(ast);
+    preprocessor::preprocess_headings(&mut node);
+    for warning in preprocessor::preprocess_xrefs(&mut node) {
+        warn!("{warning}");
+    }
+
+    if assets != AssetMode::Copy || subdir.is_some() {
+        inline::rewrite_refs(&mut node, &mut resolve_ref);
+    }
+    let (mut result, warnings) = compiler::compile_with_loader(&mut node, lang, &load, &variables)?;
+    for warning in warnings {
+        warn!("{warning}");
+    }
+
+    if assets == AssetMode::Inline && media_type == "text/html" {
+        result = inline::inline_html(&result, &mut |href| match inline::is_local(href) {
+            true => resolve_inline_asset(href, id, &locs, themes.get()).or_else(|| {
+                warn!("Inlining {href} skipped since it could not be resolved");
+                None
+            }),
+            false => {
+                warn!("Inlining {href} skipped since it is not a local asset");
+                None
+            }
+        });
+    }
+
     artifacts.insert(op.uri(), Artifact::Plaintext(media_type, result));
 
     Ok(false)
 }
 
+/// Resolve reference of a document to a data URL
+fn resolve_inline_ref(
+    scheme: &str,
+    path: &str,
+    id: &str,
+    locs: &LocationMap,
+    artifacts: &ArtifactMap,
+) -> Option<String> {
+    let (path, content) = match scheme {
+        "exec" => {
+            let key = preprocessor::resolve_scheme_path(path, "exec", artifacts.keys(), id)?;
+            match artifacts.get(&format!("exec:{key}"))? {
+                Artifact::Plaintext(mime, content) => {
+                    return Some(inline::data_url(mime, content.as_bytes()));
+                }
+                Artifact::Binary(mime, content) => return Some(inline::data_url(mime, content)),
+                Artifact::Path(path) => (path.clone(), std::fs::read(path).ok()?),
+                _ => return None,
+            }
+        }
+        _ => {
+            let key = preprocessor::resolve_path(path, locs.keys().map(String::as_str), id)?;
+            let Location::Path(path) = locs.get(key)? else {
+                return None;
+            };
+            (path.clone(), std::fs::read(path).ok()?)
+        }
+    };
+    Some(inline::data_url(inline::media_type(&path), &content))
+}
+
+/// Resolve reference of a document to the fingerprinted path of its copy, relative to the build
+/// root if given
+fn resolve_fingerprinted_ref(
+    path: &str,
+    id: &str,
+    locs: &LocationMap,
+    artifacts: &ArtifactMap,
+    root: Option<&str>,
+) -> Option<String> {
+    let key = preprocessor::resolve_path(path, locs.keys().map(String::as_str), id)?;
+    let Artifact::Path(target) = artifacts.get(&format!("copy:{key}"))? else {
+        return None;
+    };
+    let hashed = match root {
+        Some(root) => Path::new(root).join(key).with_file_name(target.file_name()?),
+        None => Path::new(path).with_file_name(target.file_name()?),
+    };
+    hashed.to_str().map(str::to_string)
+}
+
+/// Resolve reference of a document to an asset relative to the build root
+fn resolve_root_ref(
+    scheme: &str,
+    path: &str,
+    id: &str,
+    locs: &LocationMap,
+    artifacts: &ArtifactMap,
+    root: &str,
+) -> Option<String> {
+    let key = match scheme {
+        "exec" => preprocessor::resolve_scheme_path(path, "exec", artifacts.keys(), id)?,
+        _ => preprocessor::resolve_path(path, locs.keys().map(String::as_str), id)?,
+    };
+    Some(format!("{root}/{key}"))
+}
+
+/// Resolve stylesheet or script linked from a document to its content
+fn resolve_inline_asset(
+    href: &str,
+    id: &str,
+    locs: &LocationMap,
+    themes: Option<&ThemeMap>,
+) -> Option<String> {
+    // NOTE: theme assets are linked relative to the build root
+    let relative = Path::new(href)
+        .components()
+        .skip_while(|c| matches!(c, Component::CurDir | Component::ParentDir))
+        .collect::<PathBuf>();
+    if let Ok(path) = relative.strip_prefix("theme") {
+        let mut components = path.components();
+        let name = components.next()?.as_os_str().to_str()?;
+        let theme = themes?.get(name)?;
+        let content = theme.asset(components.as_path())?;
+        return String::from_utf8(content.to_vec()).ok();
+    }
+
+    let source = match locs.get(id) {
+        Some(Location::Path(path)) => path.parent().map(|p| p.join(href)),
+        _ => None,
+    };
+    let path = source.filter(|p| p.is_file()).or_else(|| {
+        let key = preprocessor::resolve_path(href, locs.keys().map(String::as_str), id)?;
+        match locs.get(key)? {
+            Location::Path(path) => Some(path.clone()),
+            Location::DataURL(_) => None,
+        }
+    })?;
+    std::fs::read_to_string(path).ok()
+}
+
+/// Resolve URI of a `LOAD` rule instruction to content of a file or artifact
+fn resolve_load(
+    uri: &str,
+    id: &str,
+    locs: &LocationMap,
+    artifacts: &ArtifactMap,
+    themes: Option<&ThemeMap>,
+) -> Option<String> {
+    match uri.split_once(':') {
+        Some(("file", _)) | None => {
+            let path = uri.trim_start_matches("file:");
+            resolve_inline_asset(path, id, locs, themes)
+                .or_else(|| std::fs::read_to_string(path).ok())
+        }
+        Some(_) => match artifacts.get(uri)? {
+            Artifact::Plaintext(_, content) => Some(content.clone()),
+            Artifact::Binary(_, content) => String::from_utf8(content.clone()).ok(),
+            Artifact::Path(path) => std::fs::read_to_string(path).ok(),
+            _ => None,
+        },
+    }
+}
+
 /// Compile AST line to string
 pub async fn compile_plaintext(
     op: Operation,
@@ -656,20 +1079,30 @@ pub async fn compile_plaintext(
         .expect("plaintext to be defined");
     let media_type = lang.media_type.clone();
 
-    let result = match ast.clone() {
-        Artifact::Ast(mut node) => compiler::compile(&mut node, lang).unwrap(),
-        Artifact::AstPointer(pointer) => {
-            let mutex = pointer.upgrade().unwrap();
-            let mut node = mutex.lock().unwrap();
-            compiler::compile(&mut node, lang).unwrap()
-        }
-        _ => panic!("compiling unknown artifact"),
-    };
+    let (mut node, _detached) = detach_loaded(ast);
+    let result = compiler::compile(&mut node, lang).unwrap();
     artifacts.insert(op.uri(), Artifact::Plaintext(media_type, result));
 
     Ok(false)
 }
 
+/// Copy an AST and fill its blocks with the data and source files it includes
+///
+/// Included ASTs are shared between documents, so the passes run on a copy.
+fn detach_loaded(ast: &Artifact) -> (Node, Detached) {
+    let (mut node, detached) = match ast {
+        Artifact::Ast(node) => node.detach(),
+        Artifact::AstPointer(pointer) => pointer.upgrade().unwrap().lock().unwrap().detach(),
+        _ => panic!("preprocessing unknown artifact"),
+    };
+    let mut warnings = preprocessor::preprocess_data(&mut node);
+    warnings.extend(preprocessor::preprocess_sources(&mut node));
+    for warning in warnings {
+        warn!("{warning}");
+    }
+    (node, detached)
+}
+
 /// Write artifact to target
 pub async fn write(
     op: Operation,
@@ -677,7 +1110,8 @@ pub async fn write(
     artifacts: Arc<Mutex<ArtifactMap>>,
     output: Output,
 ) -> Result<bool, AppError> {
-    let Operation::Write { id } = op else {
+    let uri = op.uri();
+    let Operation::Write { id, format } = op else {
         unreachable!()
     };
 
@@ -708,6 +1142,11 @@ pub async fn write(
     };
 
     if let Output::Path(root) = output {
+        // NOTE: secondary formats are written to subdirectories named after them
+        let root = match format {
+            Some(format) => root.join(&*format),
+            None => root,
+        };
         let target = match ext {
             Some(ext) => root.join(&*id).with_extension(ext),
             None => root.join(&*id),
@@ -720,14 +1159,23 @@ pub async fn write(
         }
         fs::write(&target, content)
             .await
-            .map_err(|err| AppError::write_error(err, target))?;
+            .map_err(|err| AppError::write_error(err, target.clone()))?;
+
+        // add artifact
+        let mut artifacts = artifacts.lock().expect("poisoned lock");
+        artifacts.insert(uri, Artifact::Path(target));
     }
 
     Ok(false)
 }
 
 /// Copy artifact to target
-pub async fn copy(op: Operation, output: Output) -> Result<bool, AppError> {
+pub async fn copy(
+    op: Operation,
+    assets: AssetMode,
+    artifacts: Arc<Mutex<ArtifactMap>>,
+    output: Output,
+) -> Result<bool, AppError> {
     let Operation::Copy { id, source } = op else {
         unreachable!()
     };
@@ -737,7 +1185,13 @@ pub async fn copy(op: Operation, output: Output) -> Result<bool, AppError> {
             warn!("Copying of {id} skipped since output is stdout");
         }
         Output::Path(root) => {
-            let target = root.join(&*id);
+            let target = match (&source, assets) {
+                (Source::Path(path), AssetMode::Fingerprint) => {
+                    let content = fs::read(path).await.with_ctx(path)?;
+                    root.join(fingerprint::hashed_path(Path::new(&*id), &content))
+                }
+                _ => root.join(&*id),
+            };
             match source {
                 Source::Path(path) if path == target => {
                     warn!("Copying {id} skipped since source and destination are the same");
@@ -751,7 +1205,11 @@ pub async fn copy(op: Operation, output: Output) -> Result<bool, AppError> {
                     }
                     fs::copy(path.clone(), target.clone())
                         .await
-                        .map_err(|err| AppError::copy_error(err, path, target))?;
+                        .map_err(|err| AppError::copy_error(err, path, target.clone()))?;
+
+                    // add artifact
+                    let mut artifacts = artifacts.lock().expect("poisoned lock");
+                    artifacts.insert(format!("copy:{id}"), Artifact::Path(target));
                 }
                 Source::Url(_) => todo!(),
             }
@@ -761,6 +1219,347 @@ pub async fn copy(op: Operation, output: Output) -> Result<bool, AppError> {
     Ok(false)
 }
 
+/// Write manifest of fingerprinted assets
+pub async fn fingerprint(
+    artifacts: Arc<Mutex<ArtifactMap>>,
+    output: Output,
+) -> Result<bool, AppError> {
+    let Output::Path(root) = output else {
+        warn!("Writing of asset manifest skipped since output is stdout");
+        return Ok(false);
+    };
+
+    let mut entries = {
+        let artifacts = artifacts.lock().expect("poisoned lock");
+        artifacts
+            .iter()
+            .filter_map(|(uri, artifact)| match (uri.strip_prefix("copy:"), artifact) {
+                (Some(id), Artifact::Path(target)) => {
+                    let path = target.strip_prefix(&root).unwrap_or(target);
+                    Some((id.to_string(), path.display().to_string()))
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+    };
+    entries.sort();
+    debug!("Fingerprinted {} assets", entries.len());
+
+    fs::create_dir_all(&root)
+        .await
+        .map_err(|err| AppError::write_error(err, root.clone()))?;
+
+    let target = root.join(fingerprint::MANIFEST);
+    debug!("Writing {} to {}", fingerprint::MANIFEST, target.display());
+    fs::write(&target, fingerprint::manifest(&entries))
+        .await
+        .map_err(|err| AppError::write_error(err, target.clone()))?;
+
+    // add artifact
+    let mut artifacts = artifacts.lock().expect("poisoned lock");
+    let uri = format!("fingerprint:{}", fingerprint::MANIFEST);
+    artifacts.insert(uri, Artifact::Path(target));
+
+    Ok(false)
+}
+
+/// Copy theme assets to output
+pub async fn theme(
+    op: Operation,
+    themes: Arc<OnceLock<ThemeMap>>,
+    artifacts: Arc<Mutex<ArtifactMap>>,
+    output: Output,
+) -> Result<bool, AppError> {
+    let Operation::Theme { name } = op else {
+        unreachable!()
+    };
+
+    let Output::Path(root) = output else {
+        warn!("Copying of theme {name} skipped since output is stdout");
+        return Ok(false);
+    };
+
+    let theme = themes
+        .get()
+        .expect("themes not loaded")
+        .get(&*name)
+        .ok_or(AppError::unknown_theme(name.to_string()))?;
+    let target_dir = root.join("theme").join(&*name);
+    debug!("Copying theme {name} to {}", target_dir.display());
+
+    for (path, content) in &theme.assets {
+        let target = target_dir.join(path);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)
+                .await
+                .map_err(|err| AppError::write_error(err, parent))?;
+        }
+        fs::write(&target, content)
+            .await
+            .map_err(|err| AppError::write_error(err, target.clone()))?;
+
+        // add artifact
+        let mut artifacts = artifacts.lock().expect("poisoned lock");
+        artifacts.insert(format!("theme:{name}/{}", path.display()), Artifact::Path(target));
+    }
+
+    Ok(false)
+}
+
+/// Write sitemap and feed of written documents
+pub async fn site(
+    op: Operation,
+    asts: Arc<Mutex<AstMap>>,
+    artifacts: Arc<Mutex<ArtifactMap>>,
+    output: Output,
+) -> Result<bool, AppError> {
+    let Operation::Site { base_url, title } = op else {
+        unreachable!()
+    };
+
+    let Output::Path(root) = output else {
+        warn!("Writing of sitemap and feed skipped since output is stdout");
+        return Ok(false);
+    };
+
+    let mut entries = {
+        let asts = asts.lock().expect("poisoned lock");
+        let artifacts = artifacts.lock().expect("poisoned lock");
+        artifacts
+            .iter()
+            .filter_map(|(uri, artifact)| match (uri.strip_prefix("write:"), artifact) {
+                (Some(id), Artifact::Path(target)) => Some((id, target)),
+                _ => None,
+            })
+            .filter_map(|(id, target)| {
+                // NOTE: only parsed documents are published
+                let node = asts.get(&format!("ast:{id}"))?.lock().expect("poisoned lock");
+                let prop = |key| node.find_prop(key).map(|v| v.to_string());
+                let path = target.strip_prefix(&root).unwrap_or(target);
+                Some(SiteEntry {
+                    path: path.display().to_string(),
+                    title: prop("title"),
+                    date: prop("date"),
+                    summary: prop("summary"),
+                })
+            })
+            .collect::<Vec<_>>()
+    };
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    debug!("Publishing {} documents", entries.len());
+
+    fs::create_dir_all(&root)
+        .await
+        .map_err(|err| AppError::write_error(err, root.clone()))?;
+
+    for (name, content) in [
+        ("sitemap.xml", site::sitemap(&base_url, &entries)),
+        ("atom.xml", site::feed(&base_url, &title, &entries)),
+    ] {
+        let target = root.join(name);
+        debug!("Writing {name} to {}", target.display());
+        fs::write(&target, content)
+            .await
+            .map_err(|err| AppError::write_error(err, target.clone()))?;
+
+        // add artifact
+        let mut artifacts = artifacts.lock().expect("poisoned lock");
+        artifacts.insert(format!("site:{name}"), Artifact::Path(target));
+    }
+
+    Ok(false)
+}
+
+/// Record outputs of the build and handle outputs of previous builds
+pub async fn manifest(
+    op: Operation,
+    artifacts: Arc<Mutex<ArtifactMap>>,
+    output: Output,
+) -> Result<bool, AppError> {
+    let Operation::Manifest { clean, ref sources } = op else {
+        unreachable!()
+    };
+    let sources = source_paths(sources);
+
+    let Output::Path(root) = output else {
+        return Ok(false);
+    };
+
+    // NOTE: exec artifacts are left alone since commands may write anywhere
+    let outputs = {
+        let artifacts = artifacts.lock().expect("poisoned lock");
+        artifacts
+            .iter()
+            .filter(|(uri, _)| match op::unqualify(uri).split_once(':') {
+                Some((scheme, _)) => OUTPUT_SCHEMES.contains(&scheme),
+                None => false,
+            })
+            .filter_map(|(uri, artifact)| match artifact {
+                Artifact::Path(target) => {
+                    let path = target.strip_prefix(&root).ok()?;
+                    Some((uri.clone(), path.to_path_buf()))
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let mut entries = Vec::with_capacity(outputs.len());
+    for (uri, path) in outputs {
+        let target = root.join(&path);
+        let content = fs::read(&target).await.with_ctx(&target)?;
+        entries.push(ManifestEntry {
+            uri,
+            path: path.display().to_string(),
+            hash: fingerprint::hash(&content),
+        });
+    }
+
+    let target = root.join(manifest::FILE);
+    let previous = match fs::read_to_string(&target).await {
+        Ok(content) => manifest::parse(&content),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+        Err(err) => return Err(AppError::read_error(err, target)),
+    };
+
+    let stale = manifest::stale(&previous, &entries, &sources);
+
+    // NOTE: outputs of sources outside of this build are still tracked
+    let mut kept = previous
+        .iter()
+        .filter(|prev| entries.iter().all(|e| e.path != prev.path) && !stale.contains(prev))
+        .cloned()
+        .collect::<Vec<_>>();
+    for entry in stale {
+        let path = root.join(&entry.path);
+        // NOTE: files changed since the previous build are no longer tracked
+        match fs::read(&path).await {
+            Ok(content) if fingerprint::hash(&content) != entry.hash => {
+                warn!("Stale output {} kept since it was modified", path.display());
+            }
+            Ok(_) if clean => {
+                info!("Removing stale output {}", path.display());
+                fs::remove_file(&path)
+                    .await
+                    .map_err(|err| AppError::write_error(err, path))?;
+            }
+            Ok(_) => {
+                warn!("Stale output {} kept, use --clean to remove", path.display());
+                kept.push(entry.clone());
+            }
+            Err(_) => {}
+        }
+    }
+    entries.extend(kept);
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+
+    fs::create_dir_all(&root)
+        .await
+        .map_err(|err| AppError::write_error(err, root.clone()))?;
+
+    debug!("Writing manifest of {} outputs to {}", entries.len(), target.display());
+    fs::write(&target, manifest::render(&entries))
+        .await
+        .map_err(|err| AppError::write_error(err, target))?;
+
+    Ok(false)
+}
+
+/// Get paths of built sources, relative to the current directory
+fn source_paths(sources: &[Source]) -> Vec<&Path> {
+    sources
+        .iter()
+        .filter_map(|source| match source {
+            Source::Path(path) => Some(path.strip_prefix(".").unwrap_or(path)),
+            Source::Url(_) => None,
+        })
+        .collect()
+}
+
+/// Describe what an operation would do, if it has any effects outside of memory
+pub fn plan(
+    op: &Operation,
+    format: &str,
+    languages: &OnceLock<LangMap>,
+    assets: AssetMode,
+    output: &Output,
+    operations: &OpGraph,
+) -> Option<String> {
+    let target = |path: PathBuf| match output {
+        Output::Path(root) => root.join(path).display().to_string(),
+        Output::StdOut | Output::StdOutLog => String::from("stdout"),
+    };
+
+    match op {
+        Operation::Exec { id, cmd, .. } => Some(format!("Would run `{cmd}` for {id}")),
+        Operation::Tangle { id, target: Some(path) } => {
+            Some(format!("Would tangle {id} to {}", path.display()))
+        }
+        Operation::Tangle { id, target: None } => Some(format!("Would tangle {id}")),
+        Operation::Write { id, format: qualifier } => {
+            let lang = languages.get().expect("languages not loaded").get(format)?;
+            let path = match qualifier {
+                Some(qualifier) => Path::new(&**qualifier).join(&**id),
+                None => PathBuf::from(&**id),
+            };
+            let path = match mime2ext(&lang.media_type) {
+                Some(ext) => path.with_extension(ext),
+                None => path,
+            };
+            Some(format!("Would write {id} to {}", target(path)))
+        }
+        Operation::Copy { id, source } => {
+            let path = match (source, assets) {
+                (Source::Path(path), AssetMode::Fingerprint) => {
+                    let content = std::fs::read(path).ok()?;
+                    fingerprint::hashed_path(Path::new(&**id), &content)
+                }
+                _ => PathBuf::from(&**id),
+            };
+            Some(format!("Would copy {id} to {}", target(path)))
+        }
+        Operation::Theme { name } => {
+            let path = Path::new("theme").join(&**name);
+            Some(format!("Would copy theme {name} to {}", target(path)))
+        }
+        Operation::Fingerprint => {
+            let path = PathBuf::from(fingerprint::MANIFEST);
+            Some(format!("Would write asset manifest to {}", target(path)))
+        }
+        Operation::Site { .. } => {
+            let (sitemap, feed) = (target("sitemap.xml".into()), target("atom.xml".into()));
+            Some(format!("Would write sitemap to {sitemap} and feed to {feed}"))
+        }
+        Operation::Manifest { clean, sources } => {
+            let path = PathBuf::from(manifest::FILE);
+            let mut plan = format!("Would write build manifest to {}", target(path));
+            let Output::Path(root) = output else {
+                return Some(plan);
+            };
+            let previous = std::fs::read_to_string(root.join(manifest::FILE))
+                .map(|content| manifest::parse(&content))
+                .unwrap_or_default();
+            // NOTE: outputs are not produced during a dry run, so planned ones count as current
+            let current = previous
+                .iter()
+                .filter(|prev| operations.get_uri(&prev.uri).is_some())
+                .cloned()
+                .collect::<Vec<_>>();
+            for entry in manifest::stale(&previous, &current, &source_paths(sources)) {
+                let path = root.join(&entry.path);
+                let is_unchanged = std::fs::read(&path)
+                    .is_ok_and(|content| fingerprint::hash(&content) == entry.hash);
+                let _ = match (clean, is_unchanged) {
+                    (true, true) => write!(plan, "\nWould remove stale output {}", path.display()),
+                    _ => write!(plan, "\nWould keep stale output {}", path.display()),
+                };
+            }
+            Some(plan)
+        }
+        _ => None,
+    }
+}
+
 /// Compile operations graph to PlantUML
 pub async fn graph(
     op: Operation,
@@ -807,7 +1606,7 @@ pub async fn graph(
 
 /// Finish operations
 pub async fn finish(_: Operation) -> Result<bool, AppError> {
-    Ok(true)
+    Ok(false)
 }
 
 #[cfg(test)]
@@ -4,8 +4,8 @@ use murkdown::ast::NodeBuilder;
 use murkdown::types::ExecArtifact;
 
 use crate::cli::command::GraphType;
-use crate::cli::task::{exec, gather, graph, index, preprocess, Command};
-use crate::cli::types::Source;
+use crate::cli::task::{exec, gather, graph, index, parse, preprocess, Command};
+use crate::cli::types::{AssetMode, Source};
 use crate::cli::{
     artifact::Artifact,
     op::{OpId, Operation},
@@ -37,9 +37,10 @@ async fn test_gather_adds_operationss_for_command() {
     let paths = vec!["data:,Hello%20World!#foo".to_string()];
     let sources = paths.clone().into_iter().map(Source::Url).collect();
     let op = Operation::Gather {
-        cmd: Command::Build { paths, splits: vec![], headers: None },
+        cmd: Command::Build { paths, splits: vec![], headers: None, dry_run: false },
         sources,
         splits: Some(vec![]),
+        formats: vec![None],
     };
     let ctx = State::new_loaded("markdown");
     gather(op, ctx.operations.clone()).await.unwrap();
@@ -62,6 +63,75 @@ async fn test_gather_adds_operationss_for_command() {
     );
 }
 
+#[tokio::test]
+async fn test_gather_forks_operations_per_format() {
+    let paths = vec!["data:,Hello%20World!#foo".to_string()];
+    let sources = paths.clone().into_iter().map(Source::Url).collect();
+    let op = Operation::Gather {
+        cmd: Command::Build { paths, splits: vec![], headers: None, dry_run: false },
+        sources,
+        splits: Some(vec![]),
+        formats: vec![None, Some("markdown".into())],
+    };
+    let ctx = State::new_loaded("markdown");
+    gather(op, ctx.operations.clone()).await.unwrap();
+
+    let graph = ctx.operations.lock().unwrap();
+    let mut result_uris = graph.iter().map(|(v, _, _)| v.uri()).collect::<Vec<_>>();
+    result_uris.sort();
+
+    assert_eq!(
+        result_uris,
+        [
+            "ast:foo",
+            "compile+markdown:foo",
+            "compile:foo",
+            "file:foo",
+            "finish:",
+            "gather:",
+            "parse+markdown:foo",
+            "parse:foo",
+            "write+markdown:foo",
+            "write:foo",
+        ]
+    );
+    assert_eq!(
+        graph.get_uri("parse+markdown:foo"),
+        Some(&Operation::Preprocess {
+            id: "foo".into(),
+            headers: None,
+            format: Some("markdown".into())
+        })
+    );
+}
+
+#[tokio::test]
+async fn test_gather_adds_tangle_operations_in_order() {
+    let paths = vec![
+        "data:,Hello#foo".to_string(),
+        "data:,World#bar".to_string(),
+    ];
+    let sources = paths.clone().into_iter().map(Source::Url).collect();
+    let op = Operation::Gather {
+        cmd: Command::Tangle { paths },
+        sources,
+        splits: None,
+        formats: vec![None],
+    };
+    let ctx = State::new_loaded("markdown");
+    gather(op, ctx.operations.clone()).await.unwrap();
+
+    let graph = ctx.operations.lock().unwrap();
+    assert_eq!(
+        graph.get_dependencies(&OpId::tangle("bar")),
+        [OpId::preprocess("bar"), OpId::tangle("foo")]
+    );
+    assert_eq!(
+        graph.get_dependencies(&OpId::tangle("foo")),
+        [OpId::preprocess("foo")]
+    );
+}
+
 #[tokio::test]
 async fn test_exec_returns_error_on_nonzero() {
     let op = Operation::Exec {
@@ -77,6 +147,56 @@ async fn test_exec_returns_error_on_nonzero() {
     assert!(result.is_err());
 }
 
+#[tokio::test]
+async fn test_parse_makes_preprocess_wait_for_indexed_documents() {
+    let op = Operation::Parse { id: "blog.md".into() };
+    let dep = "file:blog.md";
+    let ctx = State::new_loaded("markdown");
+    let content = "> [!INDEX](src=\"posts/\")\n".to_string();
+    ctx.insert_artifact(dep, Artifact::Plaintext("text/plain".to_string(), content));
+    ctx.insert_location("posts/a.md", PathBuf::from("posts/a.md"));
+    ctx.insert_location("posts/b.md", PathBuf::from("posts/b.md"));
+    ctx.insert_op(Operation::Parse { id: "posts/a.md".into() });
+    ctx.insert_op_chain([
+        Operation::Parse { id: "blog.md".into() },
+        Operation::Preprocess { id: "blog.md".into(), headers: None, format: None },
+    ]);
+
+    parse(
+        op,
+        "markdown".to_string(),
+        dep.to_string(),
+        ctx.asts,
+        ctx.operations.clone(),
+        ctx.artifacts,
+        ctx.languages,
+        ctx.locations,
+    )
+    .await
+    .unwrap();
+
+    let graph = ctx.operations.lock().unwrap();
+    let mut result_keys = graph.iter().map(|(v, _, _)| v).collect::<Vec<_>>();
+    result_keys.sort();
+    assert_eq!(
+        result_keys,
+        [
+            &OpId::load("posts/b.md"),
+            &OpId::parse("blog.md"),
+            &OpId::parse("posts/a.md"),
+            &OpId::parse("posts/b.md"),
+            &OpId::preprocess("blog.md"),
+        ]
+    );
+
+    // NOTE: documents that are already scheduled are waited for too
+    let deps = graph.get_dependencies(&OpId::preprocess("blog.md"));
+    assert_eq!(
+        deps,
+        [OpId::parse("blog.md"), OpId::parse("posts/a.md"), OpId::parse("posts/b.md")]
+    );
+}
+
 #[tokio::test]
 async fn test_preprocess_adds_src_operations() {
     let node = NodeBuilder::root()
@@ -84,7 +204,7 @@ async fn test_preprocess_adds_src_operations() {
             .add_prop(("src".into(), "bar".into()))
             .done()])
         .done();
-    let op = Operation::Preprocess { id: "foo".into(), headers: None };
+    let op = Operation::Preprocess { id: "foo".into(), headers: None, format: None };
     let dep = op.uri();
     let ctx = State::new_loaded("markdown");
     ctx.insert_location("bar", PathBuf::from("file.txt"));
@@ -99,6 +219,7 @@ async fn test_preprocess_adds_src_operations() {
         ctx.artifacts,
         ctx.languages,
         ctx.locations,
+        AssetMode::Copy,
     )
     .await
     .unwrap();
@@ -128,8 +249,8 @@ async fn test_preprocess_adds_src_operations() {
                 source: Source::from("file.txt")
             },
             &Operation::Parse { id: "bar".into() },
-            &Operation::Preprocess { id: "bar".into(), headers: None },
-            &Operation::Preprocess { id: "foo".into(), headers: None }
+            &Operation::Preprocess { id: "bar".into(), headers: None, format: None },
+            &Operation::Preprocess { id: "foo".into(), headers: None, format: None }
         ]
     );
 }
@@ -141,7 +262,7 @@ async fn test_preprocess_adds_ref_operations() {
             .add_prop(("ref".into(), "bar".into()))
             .done()])
         .done();
-    let op = Operation::Preprocess { id: "foo".into(), headers: None };
+    let op = Operation::Preprocess { id: "foo".into(), headers: None, format: None };
     let dep = op.uri();
     let ctx = State::new_loaded("markdown");
     ctx.insert_location("bar", PathBuf::from("file.txt"));
@@ -156,6 +277,7 @@ async fn test_preprocess_adds_ref_operations() {
         ctx.artifacts,
         ctx.languages,
         ctx.locations,
+        AssetMode::Copy,
     )
     .await
     .unwrap();
@@ -0,0 +1,114 @@
+use std::path::{Path, PathBuf};
+
+use hashbrown::HashMap;
+use walkdir::WalkDir;
+
+use super::types::{AppError, AppErrorPathCtx, ThemeMap};
+use super::utils::{is_file, is_visible};
+
+static LANG_FILE: &str = "theme.lang";
+
+type Assets = &'static [(&'static str, &'static str)];
+static BUILTIN: &[(&str, Assets)] = &[
+    ("base", &[("base.css", include_str!("themes/base/base.css"))]),
+    (
+        "simple",
+        &[
+            ("LICENSE", include_str!("themes/simple/LICENSE")),
+            ("simple.css", include_str!("themes/simple/simple.css")),
+        ],
+    ),
+    (
+        "slideshow",
+        &[
+            ("LICENSE", include_str!("themes/slideshow/LICENSE")),
+            ("slideshow.css", include_str!("themes/slideshow/slideshow.css")),
+            ("slideshow.js", include_str!("themes/slideshow/slideshow.js")),
+        ],
+    ),
+];
+
+/// Lang overlay and static assets that are copied to the output once
+#[derive(Debug, Clone, Default)]
+pub struct Theme {
+    pub lang: Option<String>,
+    pub assets: Vec<(PathBuf, Vec<u8>)>,
+}
+
+impl Theme {
+    /// Load theme from a directory
+    pub fn from_dir(dir: &Path) -> Result<Self, AppError> {
+        let mut theme = Theme::default();
+        let walker = WalkDir::new(dir)
+            .sort_by_file_name()
+            .into_iter()
+            .filter_entry(is_visible)
+            .filter_map(Result::ok)
+            .filter(is_file);
+        for entry in walker {
+            let path = entry.path();
+            let relative = path.strip_prefix(dir).expect("walked from dir");
+            if relative == Path::new(LANG_FILE) {
+                theme.lang = Some(std::fs::read_to_string(path).with_ctx(path)?);
+            } else {
+                let content = std::fs::read(path).with_ctx(path)?;
+                theme.assets.push((relative.to_path_buf(), content));
+            }
+        }
+        Ok(theme)
+    }
+
+    /// Get content of an asset
+    pub fn asset(&self, path: &Path) -> Option<&[u8]> {
+        self.assets
+            .iter()
+            .find_map(|(p, content)| (p == path).then_some(content.as_slice()))
+    }
+}
+
+/// Get name of a theme directory
+pub fn name(dir: &Path) -> Result<String, AppError> {
+    let dir = dir.canonicalize().with_ctx(dir)?;
+    match dir.file_name().and_then(|n| n.to_str()) {
+        Some(name) => Ok(name.to_string()),
+        None => Err(AppError::bad_path(dir)),
+    }
+}
+
+/// Load builtin themes and an optional theme directory
+pub fn load(dir: Option<&Path>) -> Result<ThemeMap, AppError> {
+    let mut themes = HashMap::new();
+    for (name, assets) in BUILTIN {
+        let assets = assets
+            .iter()
+            .map(|(path, content)| (PathBuf::from(path), content.as_bytes().to_vec()))
+            .collect();
+        themes.insert(name.to_string(), Theme { lang: None, assets });
+    }
+    if let Some(dir) = dir {
+        themes.insert(name(dir)?, Theme::from_dir(dir)?);
+    }
+    Ok(themes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_builtin_themes() {
+        let themes = load(None).unwrap();
+        let mut names = themes.keys().cloned().collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names, ["base", "simple", "slideshow"]);
+
+        let base = &themes["base"];
+        assert!(base.lang.is_none());
+        assert!(base.asset(Path::new("base.css")).is_some());
+        assert!(base.asset(Path::new("missing.css")).is_none());
+
+        let slideshow = &themes["slideshow"];
+        assert!(slideshow.asset(Path::new("LICENSE")).is_some());
+        assert!(slideshow.asset(Path::new("slideshow.js")).is_some());
+    }
+}
@@ -0,0 +1,25 @@
+// Slideshow navigation with keyboard, clicks and URL fragments
+(function () {
+  const slides = Array.from(document.querySelectorAll(".slides > section"));
+  let current = 0;
+
+  function show(index) {
+    current = Math.max(0, Math.min(slides.length - 1, index));
+    slides.forEach((slide, i) => slide.classList.toggle("present", i === current));
+    history.replaceState(null, "", "#" + (current + 1));
+  }
+
+  document.addEventListener("keydown", (event) => {
+    if (["ArrowRight", "ArrowDown", "PageDown", " "].includes(event.key)) show(current + 1);
+    if (["ArrowLeft", "ArrowUp", "PageUp"].includes(event.key)) show(current - 1);
+    if (event.key === "Home") show(0);
+    if (event.key === "End") show(slides.length - 1);
+  });
+
+  document.addEventListener("click", (event) => {
+    if (event.target.closest("a, input, label")) return;
+    show(current + (event.clientX < window.innerWidth / 3 ? -1 : 1));
+  });
+
+  show(parseInt(location.hash.slice(1), 10) - 1 || 0);
+})();
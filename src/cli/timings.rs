@@ -0,0 +1,168 @@
+use std::time::Duration;
+
+use hashbrown::HashMap;
+use serde_json::json;
+
+use super::op::{Op, OpId};
+
+/// How many of the slowest operations are listed in the summary
+static SLOWEST: usize = 5;
+
+/// Duration of a finished operation and size of its artifact
+#[derive(Debug, Clone)]
+pub struct Timing {
+    pub id: OpId,
+    pub duration: Duration,
+    pub size: Option<u64>,
+}
+
+/// Number of outputs and issues of a build
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Counts {
+    pub documents: usize,
+    pub assets: usize,
+    pub warnings: usize,
+}
+
+/// Sum up durations per operation kind, slowest first
+fn totals(timings: &[Timing]) -> Vec<(Op, usize, Duration)> {
+    let mut totals = HashMap::<Op, (usize, Duration)>::new();
+    for timing in timings {
+        let (count, duration) = totals.entry(timing.id.op()).or_default();
+        *count += 1;
+        *duration += timing.duration;
+    }
+    let mut totals = totals
+        .into_iter()
+        .map(|(op, (count, duration))| (op, count, duration))
+        .collect::<Vec<_>>();
+    totals.sort_by(|a, b| b.2.cmp(&a.2).then_with(|| a.0.cmp(&b.0)));
+    totals
+}
+
+fn millis(duration: Duration) -> String {
+    format!("{:.3}", duration.as_secs_f64() * 1000.0)
+}
+
+/// Summarize slowest operations and totals per operation kind
+pub fn summary(timings: &[Timing]) -> Vec<String> {
+    let mut slowest = timings.iter().collect::<Vec<_>>();
+    slowest.sort_by(|a, b| b.duration.cmp(&a.duration).then_with(|| a.id.cmp(&b.id)));
+
+    let mut lines = vec![String::from("Slowest operations:")];
+    for timing in slowest.into_iter().take(SLOWEST) {
+        lines.push(format!("  {:>10}ms {}", millis(timing.duration), timing.id.uri()));
+    }
+    lines.push(String::from("Totals per operation:"));
+    for (op, count, duration) in totals(timings) {
+        let op = format!("{op:?}");
+        lines.push(format!("  {:>10}ms {op} ({count})", millis(duration)));
+    }
+    lines
+}
+
+/// Build JSON report of per operation durations and artifact sizes
+pub fn report(timings: &[Timing], counts: Counts) -> String {
+    let mut timings = timings.iter().collect::<Vec<_>>();
+    timings.sort_by(|a, b| a.id.cmp(&b.id));
+
+    let operations = timings
+        .iter()
+        .map(|timing| {
+            json!({
+                "id": timing.id.uri(),
+                "op": format!("{:?}", timing.id.op()),
+                "duration_ms": timing.duration.as_secs_f64() * 1000.0,
+                "size": timing.size,
+            })
+        })
+        .collect::<Vec<_>>();
+    let report = json!({
+        "documents": counts.documents,
+        "assets": counts.assets,
+        "warnings": counts.warnings,
+        "operations": operations,
+    });
+    let mut out = serde_json::to_string_pretty(&report).expect("serializable report");
+    out.push('\n');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn timings() -> Vec<Timing> {
+        vec![
+            Timing {
+                id: OpId::parse("a.md"),
+                duration: Duration::from_micros(1500),
+                size: None,
+            },
+            Timing {
+                id: OpId::compile("a.md"),
+                duration: Duration::from_millis(4),
+                size: Some(120),
+            },
+            Timing {
+                id: OpId::parse("b.md"),
+                duration: Duration::from_millis(3),
+                size: None,
+            },
+        ]
+    }
+
+    #[test]
+    fn test_summary() {
+        assert_eq!(
+            summary(&timings()),
+            vec![
+                "Slowest operations:",
+                "       4.000ms compile:a.md",
+                "       3.000ms ast:b.md",
+                "       1.500ms ast:a.md",
+                "Totals per operation:",
+                "       4.500ms Parse (2)",
+                "       4.000ms Compile (1)",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_report() {
+        let counts = Counts { documents: 1, assets: 0, warnings: 2 };
+        assert_eq!(
+            report(&timings(), counts),
+            indoc! {r#"
+            {
+              "documents": 1,
+              "assets": 0,
+              "warnings": 2,
+              "operations": [
+                {
+                  "id": "ast:a.md",
+                  "op": "Parse",
+                  "duration_ms": 1.5,
+                  "size": null
+                },
+                {
+                  "id": "ast:b.md",
+                  "op": "Parse",
+                  "duration_ms": 3.0,
+                  "size": null
+                },
+                {
+                  "id": "compile:a.md",
+                  "op": "Compile",
+                  "duration_ms": 4.0,
+                  "size": 120
+                }
+              ]
+            }
+            "#}
+        );
+    }
+}
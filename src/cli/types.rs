@@ -10,7 +10,7 @@ use murkdown::{
 use thiserror::Error;
 use tokio::sync::mpsc::{self};
 
-use super::{artifact::Artifact, command::Command};
+use super::{artifact::Artifact, command::Command, theme::Theme};
 
 pub type EventTx = mpsc::UnboundedSender<Event>;
 pub type EventRx = mpsc::UnboundedReceiver<Event>;
@@ -29,6 +29,18 @@ pub type ArtifactMap = HashMap<URI, Artifact>;
 /// Map from format to language rules
 pub(crate) type LangMap = HashMap<String, Lang>;
 
+/// Map from name to theme
+pub(crate) type ThemeMap = HashMap<String, Theme>;
+
+/// How referenced assets end up in the output
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum AssetMode {
+    #[default]
+    Copy,
+    Inline,
+    Fingerprint,
+}
+
 /// Output target
 #[derive(Debug, Default, Clone)]
 pub enum Output {
@@ -200,6 +212,18 @@ pub enum AppErrorKind {
     },
     #[error("unknown language: {0}")]
     UnknownLanguage(String),
+    #[error("unknown theme: {0}")]
+    UnknownTheme(String),
+    #[error("invalid language `{path}`: {source}")]
+    BadLanguage {
+        #[backtrace]
+        source: LibError,
+        path: PathBuf,
+    },
+    #[error("cyclic language inheritance: {0}")]
+    CyclicLanguage(String),
+    #[error("lang check found {0} problems")]
+    LangCheckFailed(usize),
     #[error(transparent)]
     Lib(#[from] LibError),
 }
@@ -1,12 +1,19 @@
-use std::{fmt::Write as FmtWrite, sync::Arc};
+use std::{
+    fmt::Write as FmtWrite,
+    sync::{Arc, Mutex},
+};
 
 use derive_builder::Builder;
+use hashbrown::HashMap;
 use pest::iterators::Pair;
 
 use crate::{parser::Rule, types::Pointer};
 
 pub(crate) type Props = Vec<(Arc<str>, Arc<str>)>;
 
+/// Copies of included nodes, kept alive while a detached node points to them
+pub type Detached = Vec<Arc<Mutex<Node>>>;
+
 /// AST Node
 #[derive(Builder, Clone, Debug, Default, PartialEq, Eq)]
 #[builder(pattern = "owned", default, derive(Clone, Debug, PartialEq, Eq))]
@@ -64,6 +71,14 @@ impl Node {
         };
     }
 
+    /// Replace all values of a prop with a single value
+    pub fn set_prop(&mut self, key: &str, value: Arc<str>) {
+        if let Some(props) = self.props.as_mut() {
+            props.retain(|(k, _)| &**k != key);
+        }
+        self.add_prop(key, value);
+    }
+
     pub fn find_prop(&self, key: &str) -> Option<Arc<str>> {
         match self.props.as_ref() {
             Some(props) => props
@@ -73,6 +88,14 @@ impl Node {
         }
     }
 
+    /// Deep copy node and the nodes it includes, so that changes do not affect other documents
+    pub fn detach(&self) -> (Node, Detached) {
+        let mut copies = HashMap::new();
+        let mut node = self.clone();
+        detach_pointers(&mut node, &mut copies);
+        (node, copies.into_values().collect())
+    }
+
     /// Build AST path
     pub fn build_path(&self, prefix: &str) -> String {
         let headers = self.headers.as_ref().map_or(String::new(), |h| h.join(" "));
@@ -96,6 +119,29 @@ impl Node {
     }
 }
 
+fn detach_pointers(node: &mut Node, copies: &mut HashMap<usize, Arc<Mutex<Node>>>) {
+    if let Some(Pointer(weak)) = node.pointer.as_mut() {
+        if let Some(arc) = weak.upgrade() {
+            let key = Arc::as_ptr(&arc) as usize;
+            match copies.get(&key) {
+                Some(copy) => *weak = Arc::downgrade(copy),
+                None => {
+                    // NOTE: insert placeholder first so that cyclic pointers terminate
+                    let copy = Arc::new(Mutex::new(Node::default()));
+                    copies.insert(key, copy.clone());
+                    *weak = Arc::downgrade(&copy);
+                    let mut target = arc.lock().unwrap().clone();
+                    detach_pointers(&mut target, copies);
+                    *copy.lock().unwrap() = target;
+                }
+            }
+        }
+    }
+    for child in node.children.iter_mut().flatten() {
+        detach_pointers(child, copies);
+    }
+}
+
 impl NodeBuilder {
     pub fn new(rule: Rule) -> Self {
         Self::default().rule(rule)
@@ -168,4 +214,24 @@ mod tests {
         let node = NodeBuilder::new(Rule::Section).done();
         assert_eq!(node.build_path("[ FOO ]"), "[ FOO ] [ SEC ]");
     }
+
+    #[test]
+    fn test_detach_copies_included_nodes() {
+        let shared = Arc::new(Mutex::new(Node::line("shared")));
+        let pointer = || Some(Pointer(Arc::downgrade(&shared)));
+        let node = NodeBuilder::root()
+            .add_child(Node::ellipsis(pointer()))
+            .add_child(Node::ellipsis(pointer()))
+            .done();
+
+        let (detached, copies) = node.detach();
+        assert_eq!(copies.len(), 1);
+        copies[0].lock().unwrap().value = Some(Arc::from("changed"));
+
+        assert_eq!(shared.lock().unwrap().value.as_deref(), Some("shared"));
+        for child in detached.children.unwrap() {
+            let target = child.pointer.unwrap().0.upgrade().unwrap();
+            assert_eq!(target.lock().unwrap().value.as_deref(), Some("changed"));
+        }
+    }
 }
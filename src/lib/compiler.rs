@@ -1,16 +1,22 @@
+pub(crate) mod check;
+pub(crate) mod highlight;
 pub(crate) mod lang;
 pub(crate) mod rule;
 pub(crate) mod rule_argument;
+pub(crate) mod template;
 
-use std::collections::HashSet;
+use std::{collections::HashSet, sync::Arc};
 
+pub use check::{check, Diagnostic};
 pub use lang::Lang;
-use rule::Context;
+use highlight::Highlighter;
+use rule::{Context, Loader};
 pub(crate) use rule::Rule;
 
 use crate::ast::Node;
 use crate::parser;
-use crate::types::{Dependency, LibError, Pointer};
+use crate::source;
+use crate::types::{Dependency, LibError, LibWarning, Pointer};
 
 /// Compile AST to string
 pub fn compile(node: &mut Node, lang: &Lang) -> Result<String, LibError> {
@@ -24,6 +30,49 @@ pub fn compile(node: &mut Node, lang: &Lang) -> Result<String, LibError> {
     )
 }
 
+/// Compile AST to string along with warnings, resolving URIs of `LOAD` instructions with a loader
+///
+/// Variables are pushed to stacks before compiling, eg. paths known only to the caller.
+pub fn compile_with_loader(
+    node: &mut Node,
+    lang: &Lang,
+    load: &dyn Fn(&str) -> Option<String>,
+    variables: &[(&str, &str)],
+) -> Result<(String, Vec<LibWarning>), LibError> {
+    let mut ignored_deps = HashSet::new();
+    let mut ctx = Context { loader: Some(Loader(load)), ..Default::default() };
+    for (key, value) in variables {
+        ctx.stacks.entry(Arc::from(*key)).or_default().push((*value).into());
+    }
+    let nodes = std::slice::from_mut(&mut *node);
+    let result = compile_recusive(nodes, &mut ctx, &mut ignored_deps, lang, "")?;
+    Ok((result, ctx.warnings))
+}
+
+/// Compile blocks with a `tangle` prop to target and content pairs in document order
+pub fn tangle(node: &mut Node, lang: &Lang) -> Result<Vec<(Arc<str>, String)>, LibError> {
+    let mut result = Vec::new();
+    tangle_recursive(std::slice::from_mut(node), lang, &mut result)?;
+    Ok(result)
+}
+
+fn tangle_recursive(
+    nodes: &mut [Node],
+    lang: &Lang,
+    result: &mut Vec<(Arc<str>, String)>,
+) -> Result<(), LibError> {
+    for node in nodes.iter_mut() {
+        if let Some(target) = node.find_prop("tangle") {
+            // NOTE: compiling follows pointers, so included content is expanded
+            let content = compile(node, lang)?;
+            result.push((target, content));
+        } else if let Some(children) = node.children.as_mut() {
+            tangle_recursive(children, lang, result)?;
+        }
+    }
+    Ok(())
+}
+
 fn compile_recusive<'a>(
     nodes: &mut [Node],
     ctx: &mut Context<'a>,
@@ -32,7 +81,8 @@ fn compile_recusive<'a>(
     base_path: &str,
 ) -> Result<String, LibError> {
     let mut out = String::new();
-    let mut nodes = nodes.iter_mut().peekable();
+    // NOTE: raw lines of Murkdown documents are only kept for verbatim includes
+    let mut nodes = nodes.iter_mut().filter(|n| !source::is_raw(n)).peekable();
     let mut idx = 0;
 
     while let Some(node) = nodes.next() {
@@ -40,7 +90,7 @@ fn compile_recusive<'a>(
         ctx.set_parent(node);
         ctx.set_index(idx);
 
-        let rules = lang.get_rules("COMPILE", &path);
+        let rules = lang.get_rules("COMPILE", &path, node);
         let mut rules_stack = Vec::new();
 
         // Evaluate pre-yield
@@ -52,6 +102,16 @@ fn compile_recusive<'a>(
             rules_stack.push((instructions, settings));
         }
 
+        // NOTE: lines of a highlighted node share a highlighter, so state carries between lines
+        let is_highlighted = rules_stack.iter().any(|(_, settings)| settings.is_highlighted);
+        let outer_highlighter = is_highlighted.then(|| {
+            let last = |name| ctx.stacks.get(name).and_then(|s| s.last()).cloned();
+            let (language, style) = (last("language"), last("highlight"));
+            let highlighter =
+                Highlighter::new(&language.unwrap_or_default(), &style.unwrap_or_default());
+            std::mem::replace(&mut ctx.highlighter, highlighter)
+        });
+
         if let Some(Pointer(weak)) = &node.pointer {
             let mutex = weak.upgrade().unwrap();
             if let parser::Rule::Ellipsis = node.rule {
@@ -59,7 +119,7 @@ fn compile_recusive<'a>(
                 let mut block = mutex.lock().unwrap();
                 if let Some(children) = block.children.as_mut() {
                     // NOTE: skip section node
-                    for section in children {
+                    for section in children.iter_mut().filter(|s| !source::is_raw(s)) {
                         assert_eq!(section.rule, parser::Rule::Section);
                         if let Some(children) = section.children.as_mut() {
                             // fall through Ellipsis and only render Section contents
@@ -80,6 +140,11 @@ fn compile_recusive<'a>(
             idx += 1;
         }
 
+        if let Some(highlighter) = outer_highlighter {
+            let inner = std::mem::replace(&mut ctx.highlighter, highlighter);
+            out.push_str(&inner.map(|mut h| h.finish()).unwrap_or_default());
+        }
+
         // Evaluate post-yield
         rules_stack.reverse();
         for (mut instructions, settings) in rules_stack {
@@ -108,6 +173,8 @@ mod tests {
 
     use super::*;
     use crate::ast::NodeBuilder;
+    use crate::preprocessor;
+    use crate::types::{AstMap, LocationMap};
 
     #[test]
     fn test_compile() {
@@ -123,6 +190,51 @@ mod tests {
         assert_eq!(&result, "> foo\n");
     }
 
+    #[test]
+    fn test_compile_markdown_table() {
+        let lang = Lang::markdown();
+        let mut node = crate::parser::parse("| a \\| b | c |\n").unwrap();
+        let (mut asts, locs) = (AstMap::default(), LocationMap::default());
+        preprocessor::preprocess(&mut node, None, &mut asts, &locs, "", &lang).unwrap();
+        let result = compile(&mut node, &lang).unwrap();
+
+        assert_eq!(
+            result,
+            indoc! {
+            r#"
+            |       |     |
+            | ----- | --- |
+            | a \| b | c   |
+            "#
+            }
+        );
+    }
+
+    #[test]
+    fn test_compile_markdown_data_table() {
+        let lang = Lang::markdown();
+        let csv = crate::data::parse("Notes\nPipes | escaped\n", "notes.csv").unwrap();
+        let arc = Arc::new(Mutex::new(csv));
+        let mut node = crate::parser::parse("| [!]\n").unwrap();
+        let (mut asts, locs) = (AstMap::default(), LocationMap::default());
+        preprocessor::preprocess(&mut node, None, &mut asts, &locs, "", &lang).unwrap();
+        let block = &mut node.children.as_mut().unwrap()[0].children.as_mut().unwrap()[0];
+        block.pointer = Some(Pointer(Arc::downgrade(&arc)));
+        preprocessor::preprocess_data(&mut node);
+        let result = compile(&mut node, &lang).unwrap();
+
+        assert_eq!(
+            result,
+            indoc! {
+            r#"
+            | Notes           |
+            | --------------- |
+            | Pipes \| escaped |
+            "#
+            }
+        );
+    }
+
     #[test]
     fn test_compile_nested() {
         let lang = Lang::markdown();
@@ -283,6 +395,49 @@ mod tests {
         assert_eq!(&result, "&lt;br /&gt;");
     }
 
+    #[test]
+    fn test_tangle() {
+        let lang = Lang::new(indoc! {
+            r#"
+            RULES FOR test PRODUCE text/plain
+            COMPILE RULES:
+            LINE$
+              IS UNESCAPED_VALUE
+              WRITE "\v\n"
+            "#
+        })
+        .unwrap();
+        let mutex = Mutex::new(
+            NodeBuilder::block(">")
+                .add_section(vec![Node::line("import os")])
+                .done(),
+        );
+        let arc = Arc::new(mutex);
+        let pointer = Pointer(Arc::downgrade(&arc));
+        let mut node = NodeBuilder::root()
+            .add_section(vec![
+                Node::line("ignored"),
+                NodeBuilder::block(">")
+                    .add_prop(("tangle".into(), "main.py".into()))
+                    .add_section(vec![Node::ellipsis(Some(pointer)), Node::line("main()")])
+                    .done(),
+                NodeBuilder::block(">")
+                    .add_prop(("tangle".into(), "other.py".into()))
+                    .add_section(vec![Node::line("pass")])
+                    .done(),
+            ])
+            .done();
+
+        let result = tangle(&mut node, &lang).unwrap();
+        assert_eq!(
+            result,
+            vec![
+                (Arc::from("main.py"), "import os\nmain()\n".to_string()),
+                (Arc::from("other.py"), "pass\n".to_string()),
+            ]
+        );
+    }
+
     #[test]
     fn test_compile_skips_ellipsis_block_and_section_nodes() {
         let lang = Lang::new(indoc! {
@@ -0,0 +1,280 @@
+use std::collections::HashSet;
+use std::fmt::Display;
+
+use super::{
+    lang::Lang,
+    rule::{LangInstr, LangRule},
+    rule_argument::Arg,
+};
+
+/// Problem found in a lang
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Diagnostic {
+    pub line: usize,
+    pub message: String,
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+/// Check rules of a lang for mistakes that would otherwise surface at compile time, or not at all
+///
+/// Stacks pushed by rules of the parent lang are considered pushed.
+pub fn check(lang: &Lang, parent: Option<&Lang>) -> Vec<Diagnostic> {
+    let mut result = Vec::new();
+    for (stage, rules) in &lang.rules {
+        let parent_rules = parent.and_then(|p| p.rules.get(stage)).into_iter().flatten();
+        let pushed = rules.iter().chain(parent_rules).flat_map(pushed).collect::<HashSet<_>>();
+
+        for (idx, rule) in rules.iter().enumerate() {
+            let mut report = |message: String| {
+                result.push(Diagnostic { line: rule.line, message });
+            };
+
+            for setting in &rule.unknown_settings {
+                report(format!("unknown setting `{setting}`"));
+            }
+            for inst in rule.instructions.iter().filter(|i| !is_known(i)) {
+                report(format!("unknown instruction `{inst}`"));
+            }
+            // NOTE: only compiling has a loader, and schedules what is loaded before it
+            if *stage != "COMPILE" && rule.instructions.iter().any(|i| i.op == "LOAD") {
+                report(format!("`LOAD` is only resolved in COMPILE rules, not in {stage}"));
+            }
+            for stack in read(rule).into_iter().filter(|s| !pushed.contains(s)) {
+                report(format!("stack `{stack}` is read but never pushed"));
+            }
+            if let Some(other) = rules[..idx].iter().find(|other| shadows(other, rule)) {
+                let path = &other.path;
+                report(format!("rule is shadowed by `{path}` on line {}", other.line));
+            }
+            for stack in unbalanced(rule) {
+                report(format!("stack `{stack}` is not balanced around YIELD"));
+            }
+        }
+    }
+    result.sort();
+    result
+}
+
+/// Check if an instruction has a shape that `Lang::evaluate` knows
+// NOTE: keep in sync with `Lang::evaluate`
+fn is_known(inst: &LangInstr) -> bool {
+    use Arg::*;
+    matches!(
+        (inst.op.as_str(), inst.args.as_slice()),
+        ("DRAIN", [StackRef(_)])
+            | ("EXEC", [Str(_), MediaType(_) | File(_), URIPath(_)])
+            | ("INC" | "DEC", [StackRef(_)] | [StackRef(_), Int(_)])
+            | ("LOAD", [StackRef(_), Str(_)])
+            | ("POP", [StackRef(_) | PropRef(_)])
+            | ("PUSH", [StackRef(_), Str(_) | StackRef(_) | PropRef(_)])
+            | ("SET", [StackRef(_), Str(_)])
+            | ("SWAP", [StackRef(_), StackRef(_)])
+            | ("WRITE", [StackRef(_) | Str(_)])
+            | ("WRITEALL", [StackRef(_)])
+            | ("YIELD", _)
+            | ("NOOP", _)
+    )
+}
+
+/// Get stacks a rule pushes to
+fn pushed(rule: &LangRule) -> Vec<&str> {
+    use Arg::*;
+    rule.instructions
+        .iter()
+        .flat_map(|inst| match (inst.op.as_str(), inst.args.as_slice()) {
+            ("PUSH" | "SET" | "INC" | "DEC" | "LOAD", [StackRef(target), ..]) => {
+                vec![target.as_str()]
+            }
+            ("SWAP", [StackRef(target), StackRef(source)]) => {
+                vec![target.as_str(), source.as_str()]
+            }
+            _ => vec![],
+        })
+        .collect()
+}
+
+/// Get stacks a rule pops or reads
+fn read(rule: &LangRule) -> Vec<&str> {
+    use Arg::*;
+    let mut stacks = rule
+        .instructions
+        .iter()
+        .filter_map(|inst| match (inst.op.as_str(), inst.args.as_slice()) {
+            ("DRAIN" | "POP" | "WRITE" | "WRITEALL", [StackRef(stack)]) => Some(stack.as_str()),
+            ("PUSH", [StackRef(_), StackRef(source)]) => Some(source.as_str()),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    stacks.sort();
+    stacks.dedup();
+    stacks
+}
+
+/// Check if an earlier non-composable rule matches every path that a later rule matches
+fn shadows(earlier: &LangRule, later: &LangRule) -> bool {
+    if earlier.settings.is_composable || earlier.settings.is_dropped || !earlier.props.is_empty() {
+        return false;
+    }
+
+    // NOTE: selectors are regular expressions, so compare using sample paths of the later rule
+    let path = later.path.trim_start_matches('^').trim_end_matches('$');
+    let mut samples = vec![path.replace("...", ""), path.replace("...", "X")];
+    if !later.path.starts_with('^') {
+        samples.extend(samples.clone().into_iter().map(|s| format!("[X] {s}")));
+    }
+    if !later.path.ends_with('$') {
+        samples.extend(samples.clone().into_iter().map(|s| format!("{s} [X]")));
+    }
+    samples.iter().all(|s| later.matches(s)) && samples.iter().all(|s| earlier.matches(s))
+}
+
+/// Get stacks pushed before YIELD that are not popped after it, or vice versa
+///
+/// Composable rules are skipped, since they may be balanced by other rules of the same path, and
+/// stacks that are `SET` are skipped, since their depth depends on what was pushed before.
+fn unbalanced(rule: &LangRule) -> Vec<&str> {
+    use Arg::*;
+    if rule.settings.is_composable {
+        return vec![];
+    }
+    let Some(split) = rule.instructions.iter().position(|i| i.op == "YIELD") else {
+        return vec![];
+    };
+    let (before, after) = rule.instructions.split_at(split);
+
+    let count = |instructions: &[LangInstr], op: &str, stack: &str| {
+        instructions
+            .iter()
+            .filter(|i| i.op == op && matches!(i.args.first(), Some(StackRef(s)) if s == stack))
+            .count()
+    };
+    let skipped = |stack: &str| {
+        count(after, "DRAIN", stack) > 0 || count(&rule.instructions, "SET", stack) > 0
+    };
+
+    let mut stacks = rule
+        .instructions
+        .iter()
+        .filter(|i| i.op == "PUSH" || i.op == "POP")
+        .filter_map(|i| match i.args.first() {
+            Some(StackRef(stack)) => Some(stack.as_str()),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    stacks.sort();
+    stacks.dedup();
+    stacks
+        .into_iter()
+        .filter(|s| !skipped(s))
+        .filter(|s| {
+            let pushes = count(before, "PUSH", s) as isize - count(before, "POP", s) as isize;
+            let pops = count(after, "POP", s) as isize - count(after, "PUSH", s) as isize;
+            pushes != pops
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn messages(lang: &Lang, parent: Option<&Lang>) -> Vec<String> {
+        check(lang, parent).iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn test_check_builtin_langs() {
+        for input in [
+            include_str!("markdown.lang"),
+            include_str!("html.lang"),
+            include_str!("plaintext.lang"),
+        ] {
+            let lang = Lang::new(input).unwrap();
+            assert_eq!(messages(&lang, None), Vec::<String>::new());
+        }
+    }
+
+    #[test]
+    fn test_check() {
+        let lang = Lang::new(indoc! {
+            r#"
+            RULES FOR test PRODUCE text/plain
+            COMPILE RULES:
+            [TIP]$
+              IS SHINY
+              PUSH prefix "> "
+              YIELD
+            [...]$
+              WRITE missing
+              WRITEALL prefix
+              SWAP prefix
+            [NOTE]$
+              WRITE "note"
+            "#
+        })
+        .unwrap();
+        assert_eq!(
+            messages(&lang, None),
+            [
+                "line 3: stack `prefix` is not balanced around YIELD",
+                "line 3: unknown setting `SHINY`",
+                "line 7: stack `missing` is read but never pushed",
+                "line 7: unknown instruction `SWAP prefix`",
+                "line 11: rule is shadowed by `[...]$` on line 7",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_check_load_outside_compile() {
+        let lang = Lang::new(indoc! {
+            r#"
+            RULES FOR test PRODUCE text/plain
+            PREPROCESS RULES:
+            [STYLE]$
+              LOAD style "file:style.css"
+            COMPILE RULES:
+            [STYLE]$
+              LOAD style "file:style.css"
+              WRITE style
+            "#
+        })
+        .unwrap();
+        assert_eq!(
+            messages(&lang, None),
+            ["line 3: `LOAD` is only resolved in COMPILE rules, not in PREPROCESS"]
+        );
+    }
+
+    #[test]
+    fn test_check_with_parent() {
+        let parent = Lang::new(indoc! {
+            r#"
+            RULES FOR base PRODUCE text/plain
+            COMPILE RULES:
+            [...]$
+              PUSH prefix "> "
+            "#
+        })
+        .unwrap();
+        let lang = Lang::new(indoc! {
+            r#"
+            RULES FOR child EXTENDS base PRODUCE text/plain
+            COMPILE RULES:
+            [TIP]$
+              WRITEALL prefix
+            "#
+        })
+        .unwrap();
+        assert_eq!(messages(&lang, Some(&parent)), Vec::<String>::new());
+        assert_eq!(messages(&lang, None), ["line 3: stack `prefix` is read but never pushed"]);
+    }
+}
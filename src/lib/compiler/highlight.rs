@@ -0,0 +1,124 @@
+use std::sync::LazyLock;
+
+use syntect::{
+    highlighting::{HighlightIterator, HighlightState, Highlighter as ThemeHighlighter, ThemeSet},
+    html::{
+        line_tokens_to_classed_spans, styled_line_to_highlighted_html, ClassStyle,
+        IncludeBackground,
+    },
+    parsing::{ParseState, ScopeStack, SyntaxSet},
+};
+
+use crate::compiler::template;
+
+static SYNTAXES: LazyLock<SyntaxSet> = LazyLock::new(SyntaxSet::load_defaults_nonewlines);
+static THEMES: LazyLock<ThemeSet> = LazyLock::new(ThemeSet::load_defaults);
+
+/// Prefix of classes of highlighted spans, like `hl-keyword`
+static CLASS_PREFIX: &str = "hl-";
+
+/// Highlights lines of code to HTML, keeping state between lines
+#[derive(Debug, Clone)]
+pub(crate) struct Highlighter {
+    parse: ParseState,
+    style: Style,
+}
+
+#[derive(Debug, Clone)]
+enum Style {
+    /// Spans with classes of scopes, which stay open across lines until finished
+    Classes(ScopeStack),
+    /// Spans with inline styles of a theme
+    Inline(&'static str, HighlightState),
+}
+
+impl Highlighter {
+    /// Create a highlighter for a language, styled by classes or by the name of a bundled theme
+    ///
+    /// Returns `None` if the language is unknown, or if highlighting is turned off by `none`.
+    pub fn new(language: &str, style: &str) -> Option<Self> {
+        if language.is_empty() || style == "none" {
+            return None;
+        }
+        let syntax = SYNTAXES.find_syntax_by_token(language)?;
+        let style = match style {
+            "" | "classes" => Style::Classes(ScopeStack::new()),
+            name => {
+                let (name, theme) = THEMES.themes.get_key_value(name)?;
+                let highlighter = ThemeHighlighter::new(theme);
+                Style::Inline(name, HighlightState::new(&highlighter, ScopeStack::new()))
+            }
+        };
+        Some(Self { parse: ParseState::new(syntax), style })
+    }
+
+    /// Highlight a line to escaped HTML, or only escape it if it can not be parsed
+    pub fn line(&mut self, line: &str) -> String {
+        let Ok(ops) = self.parse.parse_line(line, &SYNTAXES) else {
+            return template::escape_default(line, "");
+        };
+        let html = match &mut self.style {
+            Style::Classes(scopes) => {
+                let class_style = ClassStyle::SpacedPrefixed { prefix: CLASS_PREFIX };
+                let spans = line_tokens_to_classed_spans(line, &ops, class_style, scopes);
+                spans.map(|(spans, _)| spans)
+            }
+            Style::Inline(name, state) => {
+                let highlighter = ThemeHighlighter::new(&THEMES.themes[*name]);
+                let regions = HighlightIterator::new(state, &ops, line, &highlighter);
+                let regions = regions.collect::<Vec<_>>();
+                styled_line_to_highlighted_html(&regions, IncludeBackground::No)
+            }
+        };
+        html.unwrap_or_else(|_| template::escape_default(line, ""))
+    }
+
+    /// Close spans left open by the highlighted lines
+    pub fn finish(&mut self) -> String {
+        match &mut self.style {
+            Style::Classes(scopes) => "</span>".repeat(std::mem::take(scopes).len()),
+            Style::Inline(..) => String::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_highlight_classes() {
+        let mut highlighter = Highlighter::new("rust", "classes").unwrap();
+        let first = highlighter.line("let x = 1; /* a");
+        assert!(first.starts_with(r#"<span class="hl-source hl-rust"><span class="hl-storage"#));
+        assert!(first.contains(r#"<span class="hl-comment hl-block hl-rust">"#));
+        assert!(first.matches("<span").count() > first.matches("</span>").count());
+
+        // NOTE: spans open across lines are continued, not reopened
+        let second = highlighter.line("b */");
+        assert!(second.starts_with("b "));
+        assert!(!second.contains("hl-source"));
+
+        let html = [first, second, highlighter.finish()].concat();
+        assert_eq!(html.matches("<span").count(), html.matches("</span>").count());
+        assert_eq!(highlighter.finish(), "");
+    }
+
+    #[test]
+    fn test_highlight_inline() {
+        let mut highlighter = Highlighter::new("py", "InspiredGitHub").unwrap();
+        let html = highlighter.line("x = \"<a>\"");
+        assert!(html.contains("<span style=\"color:#"));
+        assert!(html.contains("&lt;a&gt;"));
+    }
+
+    #[test]
+    fn test_highlight_unknown() {
+        assert!(Highlighter::new("nonexistent", "classes").is_none());
+        assert!(Highlighter::new("rust", "nonexistent").is_none());
+        assert!(Highlighter::new("rust", "none").is_none());
+        assert!(Highlighter::new("", "classes").is_none());
+    }
+}
@@ -1,34 +1,56 @@
-use std::sync::OnceLock;
 use std::{borrow::Cow, collections::HashSet, sync::Arc};
 
-use htmlize::escape_text;
 use itertools::Itertools;
-use regex::Regex;
 
 use super::{
-    rule::{self, Context, LangInstr, LangRule, LangSettings},
+    rule::{self, Context, LangInstr, LangRule, LangSettings, Loader},
     rule_argument::Arg,
+    template,
 };
 use crate::{
     ast::Node,
-    types::{Dependency, ExecArtifact, LibError, RuleMap},
+    parser,
+    types::{Dependency, ExecArtifact, LibError, LibWarning, RuleMap},
 };
 
 #[derive(Debug, Clone)]
 pub struct Lang {
     pub name: String,
+    pub extends: Option<String>,
     pub media_type: String,
     pub(crate) rules: RuleMap,
 }
 
-static VARIABLE_RE: OnceLock<Regex> = OnceLock::new();
-
 /// A set of compiler rules
 impl Lang {
     pub fn new(input: &str) -> Result<Lang, LibError> {
-        let (name, media_type, rules) = rule::parse(input)?;
+        let (name, extends, media_type, rules) = rule::parse(input)?;
+
+        Ok(Lang { name, extends, rules, media_type })
+    }
+
+    /// Inherit rules of a parent lang, so that own rules are tried first
+    ///
+    /// Parent rules with the same path as an `IS OVERRIDING` or `IS DROPPED` rule are removed,
+    /// and dropped rules are removed as well.
+    pub fn extend(&mut self, parent: &Lang) {
+        for (stage, parent_rules) in &parent.rules {
+            let rules = self.rules.entry(stage).or_default();
+            let replaced = rules
+                .iter()
+                .filter(|r| r.settings.is_overriding || r.settings.is_dropped)
+                .map(|r| r.path.clone())
+                .collect::<HashSet<_>>();
+            rules.retain(|r| !r.settings.is_dropped);
+            rules.extend(parent_rules.iter().filter(|r| !replaced.contains(&r.path)).cloned());
+        }
+    }
 
-        Ok(Lang { name, rules, media_type })
+    /// Overlay rules of another lang, so that they take precedence
+    pub fn overlay(&mut self, other: Lang) {
+        for (stage, rules) in other.rules {
+            self.rules.entry(stage).or_default().splice(0..0, rules);
+        }
     }
 
     #[cfg(test)]
@@ -37,19 +59,22 @@ impl Lang {
             .expect("builtin markdown to work")
     }
 
-    /// Get rules for an AST path
+    /// Get rules for an AST path and its node
     pub(crate) fn get_rules(
         &self,
         stage: &'static str,
         path: &str,
+        node: &Node,
     ) -> impl Iterator<Item = &LangRule> {
         let rules = self.rules.get(stage);
-        let path = path.to_string();
+        // NOTE: rules are collected, so the node is free to be mutated by their instructions
         rules
             .unwrap()
             .iter()
-            .filter(move |r| r.matches(&path))
+            .filter(|r| r.matches_node(path, node))
             .take_while_inclusive(|&v| v.settings.is_composable)
+            .collect::<Vec<_>>()
+            .into_iter()
     }
 
     /// Get instructions for an AST path
@@ -85,13 +110,15 @@ impl Lang {
                     }
                 }
                 ("EXEC", [Str(cmd), destination @ (MediaType(_) | File(_)), URIPath(id)]) => {
-                    let cmd = replace(cmd, ctx, &*node, set).to_string();
-                    let id = replace(id, ctx, &*node, set).to_string();
+                    let cmd = replace(cmd, ctx, &*node, set)?.to_string();
+                    let id = replace(id, ctx, &*node, set)?.to_string();
                     let artifact = match destination {
                         MediaType(t) => {
-                            ExecArtifact::Stdout(replace(t, ctx, &*node, set).to_string())
+                            ExecArtifact::Stdout(replace(t, ctx, &*node, set)?.to_string())
+                        }
+                        File(p) => {
+                            ExecArtifact::Path(replace(p, ctx, &*node, set)?.as_ref().into())
                         }
-                        File(p) => ExecArtifact::Path(replace(p, ctx, &*node, set).as_ref().into()),
                         _ => unreachable!(),
                     };
                     let input = node.children.as_ref().map(|children| {
@@ -104,6 +131,33 @@ impl Lang {
                     });
                     deps.insert(Dependency::Exec { cmd, input, id, artifact });
                 }
+                (op @ ("INC" | "DEC"), [StackRef(target), step @ ..])
+                    if matches!(step, [] | [Int(_)]) =>
+                {
+                    let step = match step {
+                        [Int(step)] => *step,
+                        _ => 1,
+                    };
+                    let step = if op == "DEC" { -step } else { step };
+                    let stack = ctx.stacks.entry_ref(target.as_str()).or_insert(vec![]);
+                    // NOTE: missing or non-numeric counters start from zero
+                    let value = stack.pop().and_then(|v| v.parse::<i64>().ok()).unwrap_or(0);
+                    stack.push(Cow::Owned((value + step).to_string()));
+                }
+                ("LOAD", [StackRef(target), Str(uri)]) => {
+                    let uri = replace(uri, ctx, &*node, set)?.to_string();
+                    let dep = match uri.contains(':') {
+                        true => uri.clone(),
+                        false => format!("file:{uri}"),
+                    };
+                    deps.insert(Dependency::URI("src", dep));
+                    let content = ctx.loader.and_then(|Loader(load)| load(&uri));
+                    let content = content.ok_or_else(|| LibError::unresolved_load(uri))?;
+                    ctx.stacks
+                        .entry_ref(target.as_str())
+                        .or_insert(vec![])
+                        .push(Cow::Owned(content));
+                }
                 ("POP", [StackRef(stack)]) => {
                     if let Some(stack) = ctx.stacks.get_mut(stack.as_str()) {
                         stack.pop();
@@ -119,7 +173,7 @@ impl Lang {
                 ("PUSH", [StackRef(target), Str(value)])
                     if ["src", "ref"].contains(&target.as_str()) =>
                 {
-                    let value = replace(value, ctx, &*node, set);
+                    let value = replace(value, ctx, &*node, set)?;
                     ctx.stacks
                         .entry(Arc::from(target.as_str()))
                         .or_default()
@@ -127,7 +181,7 @@ impl Lang {
                     node.add_prop(target.as_str(), Arc::from(value));
                 }
                 ("PUSH", [StackRef(target), Str(value)]) => {
-                    let value = replace(value, ctx, &*node, set);
+                    let value = replace(value, ctx, &*node, set)?;
                     ctx.stacks
                         .entry(Arc::from(target.as_str()))
                         .or_default()
@@ -156,7 +210,7 @@ impl Lang {
                     }
                 }
                 ("SET", [StackRef(target), Str(value)]) => {
-                    let value = replace(value, ctx, node, set);
+                    let value = replace(value, ctx, node, set)?;
                     let v = ctx.stacks.entry_ref(target.as_str()).or_insert(vec![]);
                     //.or_insert(Arc::from(target.as_str()), vec![])
                     v.pop();
@@ -179,7 +233,7 @@ impl Lang {
                         out.push_str(value);
                     }
                 }
-                ("WRITE", [Str(value)]) => out.push_str(&replace(value, ctx, node, set)),
+                ("WRITE", [Str(value)]) => out.push_str(&replace(value, ctx, node, set)?),
                 ("WRITEALL", [StackRef(stack)]) => {
                     let stack = ctx.stacks.get(stack.as_str());
                     if let Some(stack) = stack {
@@ -202,67 +256,69 @@ fn replace<'a>(
     ctx: &mut Context,
     node: &Node,
     settings: &LangSettings,
-) -> Cow<'a, str> {
-    // template without substititions
-    if !template.contains(r"\") && !template.contains("$") {
-        return Cow::Borrowed(template);
-    }
-
-    let value = match settings.is_unescaped_value {
-        true => node.value.as_deref().map(Cow::Borrowed),
-        false => node.value.as_deref().map(escape_text).to_owned(),
-    };
-
-    // escapes
-    let mut result = template.replace(r#"\""#, "\"").replace(r"\n", "\n");
+) -> Result<Cow<'a, str>, LibError> {
+    use template::Token::*;
 
-    // variables from props
-    if template.contains("$") {
-        for (key, value) in node.props.iter().flatten() {
-            result = result.replace(&["$", key].concat(), value);
-        }
-    }
-
-    // variables from stacks with modifiers
-    if result.contains("$") && result.contains(":") {
-        for (key, stack) in ctx.stacks.iter() {
-            result = result.replace(&["$", key, ":j"].concat(), &stack.join(" "));
-        }
+    // template without substititions
+    if !template.contains(['\\', '$']) {
+        return Ok(Cow::Borrowed(template));
     }
 
-    // variables from stacks
-    if result.contains("$") {
-        for (key, stack) in ctx.stacks.iter() {
-            if let Some(last) = stack.last() {
-                result = result.replace(&["$", key].concat(), last);
+    let mut result = String::with_capacity(template.len());
+    for token in template::tokenize(template)? {
+        match token {
+            Text(text) => result.push_str(text),
+            Char(c) => result.push(c),
+            Variable(name, modifiers) => {
+                // NOTE: props take precedence over stacks, unless stacks are joined
+                let stack = ctx.stacks.get(name);
+                let value = match template::is_joining(modifiers) {
+                    true => Some(stack.map(|s| s.join(" ")).unwrap_or_default()),
+                    false => node
+                        .find_prop(name)
+                        .map(|v| v.to_string())
+                        .or_else(|| stack.and_then(|s| s.last()).map(|v| v.to_string())),
+                };
+                let warning = LibWarning::UnsetVariable(name.to_string());
+                if value.is_none()
+                    && !template::has_default(modifiers)
+                    && !ctx.warnings.contains(&warning)
+                {
+                    ctx.warnings.push(warning);
+                }
+                result.push_str(&template::apply(value.as_deref(), modifiers));
+            }
+            Builtin('r', _) => result.push_str(&ctx.rng.sample_string()),
+            Builtin('i', modifiers) => {
+                result.push_str(&template::apply(Some(&ctx.index.to_string()), modifiers))
+            }
+            Builtin('v', modifiers) => {
+                let value = template::apply(node.value.as_deref(), modifiers);
+                match (&mut ctx.highlighter, settings.is_unescaped_value) {
+                    (Some(highlighter), _) if node.rule == parser::Rule::Line => {
+                        result.push_str(&highlighter.line(&value))
+                    }
+                    (_, true) => result.push_str(&value),
+                    (_, false) => result.push_str(&template::escape_default(&value, modifiers)),
+                }
+            }
+            Builtin('V', modifiers) => {
+                result.push_str(&template::apply(ctx.parent_value.as_deref(), modifiers))
+            }
+            Builtin('m', modifiers) => {
+                result.push_str(&template::apply(node.marker.as_deref(), modifiers))
+            }
+            Builtin(c, modifiers) => {
+                let headers = match c {
+                    'h' => node.headers.as_ref(),
+                    _ => ctx.parent_headers.as_ref(),
+                };
+                let headers = headers.map(|h| h.join(" "));
+                result.push_str(&template::apply(headers.as_deref(), modifiers))
             }
         }
     }
-
-    // unset variables
-    if result.contains("$") {
-        let re = VARIABLE_RE.get_or_init(|| Regex::new(r"\$(\w+)(?::([j]))?").unwrap());
-        result = re.replace(&result, "").to_string();
-    }
-
-    // builtin
-    result = result
-        .replace(r"\r", &ctx.rng.sample_string())
-        .replace(r"\v", value.as_deref().unwrap_or_default())
-        .replace(r"\i", &ctx.index.to_string())
-        .replace(r"\V", ctx.parent_value.as_deref().unwrap_or_default())
-        .replace(r"\m", node.marker.as_deref().unwrap_or_default());
-
-    if result.contains(r"\h") {
-        let headers = node.headers.as_ref();
-        result = result.replace(r"\h:j", &headers.map(|h| h.join(" ")).unwrap_or_default());
-    }
-    if result.contains(r"\H") {
-        let headers = ctx.parent_headers.as_ref();
-        result = result.replace(r"\H:j", &headers.map(|h| h.join(" ")).unwrap_or_default());
-    }
-
-    Cow::Owned(result)
+    Ok(Cow::Owned(result))
 }
 
 #[cfg(test)]
@@ -309,10 +365,84 @@ mod tests {
         };
         let lang = Lang::new(input).unwrap();
 
-        let rules = lang.get_rules("PREPROCESS", "[FOO] [SEC]");
+        let rules = lang.get_rules("PREPROCESS", "[FOO] [SEC]", &Node::default());
         assert_eq!(rules.count(), 2);
     }
 
+    #[test]
+    fn test_extend() {
+        let parent = Lang::new(indoc! {
+            r#"
+            RULES FOR base PRODUCE text/plain
+            COMPILE RULES:
+            [TIP]$
+              WRITE "tip"
+            [NOTE]$
+              WRITE "note"
+            [WARNING]$
+              WRITE "warning"
+            [...]$
+              WRITE "other"
+            "#
+        })
+        .unwrap();
+        let mut lang = Lang::new(indoc! {
+            r#"
+            RULES FOR child EXTENDS base PRODUCE text/plain
+            COMPILE RULES:
+            [TIP]$
+              IS COMPOSABLE
+              WRITE "child "
+            [NOTE]$
+              IS OVERRIDING
+              WRITE "child note"
+            [WARNING]$
+              IS DROPPED
+            "#
+        })
+        .unwrap();
+        assert_eq!(lang.extends.as_deref(), Some("base"));
+        lang.extend(&parent);
+
+        let instructions = |path| {
+            lang.get_rules("COMPILE", path, &Node::default())
+                .flat_map(|r| r.instructions.iter().map(ToString::to_string))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(instructions("[TIP]"), ["WRITE \"child \"", "WRITE \"tip\""]);
+        assert_eq!(instructions("[NOTE]"), ["WRITE \"child note\""]);
+        assert_eq!(instructions("[WARNING]"), ["WRITE \"other\""]);
+    }
+
+    #[test]
+    fn test_overlay() {
+        let mut lang = Lang::new(indoc! {
+            r#"
+            RULES FOR test PRODUCE text/plain
+            COMPILE RULES:
+            [SEC...]$
+              WRITE "base"
+            "#
+        })
+        .unwrap();
+        let overlay = Lang::new(indoc! {
+            r#"
+            RULES FOR theme PRODUCE text/plain
+            COMPILE RULES:
+            [SEC]$
+              WRITE "theme"
+            "#
+        })
+        .unwrap();
+        lang.overlay(overlay);
+
+        assert_eq!(lang.name, "test");
+        let (instructions, _) = lang.get_instructions("COMPILE", "[SEC]");
+        assert_eq!(instructions.map(ToString::to_string).collect::<Vec<_>>(), ["WRITE \"theme\""]);
+        let (instructions, _) = lang.get_instructions("COMPILE", "[SEC FOO]");
+        assert_eq!(instructions.map(ToString::to_string).collect::<Vec<_>>(), ["WRITE \"base\""]);
+    }
+
     #[test]
     fn test_evaluate() {
         let input = indoc! {
@@ -382,12 +512,103 @@ mod tests {
         };
         let mut deps = HashSet::new();
         let lang = Lang::new(input).unwrap();
+        let mut node = Node { value: Some("value".into()), ..Default::default() };
+
+        let (mut instructions, settings) = lang.get_instructions("COMPILE", "[rule]");
+        let mut ctx = Context { parent_value: Some("parent value".into()), ..Default::default() };
+        ctx.stacks
+            .entry(Arc::from("var"))
+            .or_default()
+            .extend(["foo".into(), "bar".into()]);
+
+        let value = lang
+            .evaluate(&mut instructions, &mut ctx, &mut deps, &mut node, &settings)
+            .unwrap();
+        assert_eq!(value, "bar\nfoo bar\nvalue and parent value");
+    }
+
+    #[test]
+    fn test_evaluate_counters() {
+        let input = indoc! {
+            r#"
+            RULES FOR test PRODUCE text/plain
+            COMPILE RULES:
+            [rule]
+              INC figure
+              INC figure
+              WRITE "$figure "
+              DEC figure
+              WRITE "$figure "
+              SET total "nan"
+              INC total 10
+              WRITE "$total"
+            "#
+        };
+        let mut deps = HashSet::new();
+        let lang = Lang::new(input).unwrap();
         let mut node = Node::default();
-        node.value = Some("value".into());
 
         let (mut instructions, settings) = lang.get_instructions("COMPILE", "[rule]");
         let mut ctx = Context::default();
-        ctx.parent_value = Some("parent value".into());
+
+        let value = lang
+            .evaluate(&mut instructions, &mut ctx, &mut deps, &mut node, &settings)
+            .unwrap();
+        assert_eq!(value, "2 1 10");
+        assert_eq!(ctx.stacks.get("figure").map(Vec::len), Some(1));
+    }
+
+    #[test]
+    fn test_evaluate_load() {
+        let input = indoc! {
+            r#"
+            RULES FOR test PRODUCE text/plain
+            COMPILE RULES:
+            [rule]
+              LOAD style "file:$name.css"
+              WRITE "<style>$style</style>"
+            "#
+        };
+        let mut deps = HashSet::new();
+        let lang = Lang::new(input).unwrap();
+        let mut node = NodeBuilder::root().add_prop(("name".into(), "main".into())).done();
+
+        let load = |uri: &str| (uri == "file:main.css").then(|| String::from("p {}"));
+        let (mut instructions, settings) = lang.get_instructions("COMPILE", "[rule]");
+        let mut ctx = Context { loader: Some(Loader(&load)), ..Default::default() };
+        let value = lang
+            .evaluate(&mut instructions, &mut ctx, &mut deps, &mut node, &settings)
+            .unwrap();
+        assert_eq!(value, "<style>p {}</style>");
+        assert!(deps.contains(&Dependency::URI("src", "file:main.css".to_string())));
+
+        let (mut instructions, settings) = lang.get_instructions("COMPILE", "[rule]");
+        let mut ctx = Context::default();
+        let err = lang
+            .evaluate(&mut instructions, &mut ctx, &mut deps, &mut node, &settings)
+            .unwrap_err();
+        assert_eq!(err.to_string(), "could not load `file:main.css`");
+    }
+
+    #[test]
+    fn test_evaluate_modifiers() {
+        let input = indoc! {
+            r#"
+            RULES FOR test PRODUCE text/plain
+            COMPILE RULES:
+            [rule]
+              WRITE "<h2 id=\"\v:slug\" title=\"\v:attr\">\v:u</h2>\n"
+              WRITE "$title:d(Untitled) $var:u $var:j:u $missing:d(none) $name:u $name\n"
+              WRITE "{\"q\": \"\V:json\"} ?q=\V:url"
+            "#
+        };
+        let mut deps = HashSet::new();
+        let lang = Lang::new(input).unwrap();
+        let mut node = Node { value: Some("Tom & \"Jerry\"".into()), ..Default::default() };
+        node.add_prop("name", "tom".into());
+
+        let (mut instructions, settings) = lang.get_instructions("COMPILE", "[rule]");
+        let mut ctx = Context { parent_value: Some("a \"b\"".into()), ..Default::default() };
         ctx.stacks
             .entry(Arc::from("var"))
             .or_default()
@@ -396,6 +617,55 @@ mod tests {
         let value = lang
             .evaluate(&mut instructions, &mut ctx, &mut deps, &mut node, &settings)
             .unwrap();
-        assert_eq!(value, "bar\nfoo bar\nvalue and parent value");
+        assert_eq!(
+            value,
+            concat!(
+                "<h2 id=\"tom-jerry\" title=\"Tom &amp; &quot;Jerry&quot;\">",
+                "TOM &amp; \"JERRY\"</h2>\n",
+                "Untitled BAR FOO BAR none TOM tom\n",
+                "{\"q\": \"a \\\"b\\\"\"} ?q=a%20%22b%22",
+            )
+        );
+    }
+
+    #[test]
+    fn test_evaluate_single_pass() {
+        let input = indoc! {
+            r#"
+            RULES FOR test PRODUCE text/plain
+            COMPILE RULES:
+            [rule]
+              WRITE "$tab $tabid ${tab}id $missing.\v"
+            "#
+        };
+        let mut deps = HashSet::new();
+        let lang = Lang::new(input).unwrap();
+        let mut node = NodeBuilder::root()
+            .add_prop(("tab".into(), "$tabid".into()))
+            .add_prop(("tabid".into(), "t1".into()))
+            .done();
+        node.value = Some("\\v".into());
+
+        let (mut instructions, settings) = lang.get_instructions("COMPILE", "[rule]");
+        let mut ctx = Context::default();
+        let value = lang
+            .evaluate(&mut instructions, &mut ctx, &mut deps, &mut node, &settings)
+            .unwrap();
+        assert_eq!(value, "$tabid t1 $tabidid .\\v");
+        assert_eq!(ctx.warnings, vec![LibWarning::UnsetVariable("missing".to_string())]);
+    }
+
+    #[test]
+    fn test_malformed_template() {
+        let input = indoc! {
+            r#"
+            RULES FOR test PRODUCE text/plain
+            COMPILE RULES:
+            [rule]
+              WRITE "${title"
+            "#
+        };
+        let err = Lang::new(input).unwrap_err();
+        assert_eq!(err.to_string(), "invalid template `${title`: unclosed `${`");
     }
 }
@@ -12,8 +12,10 @@ use rand::SeedableRng;
 use regex::Regex;
 
 use crate::ast::Node;
+use crate::compiler::highlight::Highlighter;
 use crate::compiler::rule_argument::Arg;
-use crate::types::{LibError, RuleMap};
+use crate::compiler::template;
+use crate::types::{LibError, LibWarning, RuleMap};
 
 #[derive(Parser)]
 #[grammar = "lib/compiler/rule_grammar.pest"]
@@ -22,17 +24,27 @@ struct RawRuleParser;
 /// Compiler rule
 #[derive(Debug, Clone)]
 pub(crate) struct LangRule {
-    #[allow(unused)]
-    path: String,
+    pub path: String,
     regex: Regex,
     pub instructions: Vec<LangInstr>,
     pub settings: LangSettings,
+    /// Settings that are not recognized, and thus ignored
+    pub unknown_settings: Vec<String>,
+    /// Props that a node must have, eg. `number` in `[...CODE...](number)$`
+    pub props: Vec<String>,
+    /// Line of the rule in the lang source
+    pub line: usize,
 }
 
 impl LangRule {
     pub fn matches(&self, path: &str) -> bool {
         self.regex.is_match(path)
     }
+
+    /// Check if a rule matches a path and the props of its node
+    pub fn matches_node(&self, path: &str, node: &Node) -> bool {
+        self.matches(path) && self.props.iter().all(|p| node.find_prop(p).is_some())
+    }
 }
 
 /// Language rule instruction
@@ -59,6 +71,9 @@ pub struct LangSettings {
     pub is_composable: bool,
     pub is_paragraphable: bool,
     pub is_unescaped_value: bool,
+    pub is_overriding: bool,
+    pub is_dropped: bool,
+    pub is_highlighted: bool,
     pub default_src: Option<&'static str>,
     pub default_ref: Option<&'static str>,
 }
@@ -68,6 +83,7 @@ impl LangSettings {
         self.is_composable = self.is_composable || other.is_composable;
         self.is_paragraphable = self.is_paragraphable || other.is_paragraphable;
         self.is_unescaped_value = self.is_unescaped_value || other.is_unescaped_value;
+        self.is_highlighted = self.is_highlighted || other.is_highlighted;
     }
 }
 
@@ -79,6 +95,21 @@ pub struct Context<'a> {
     pub parent_value: Option<Arc<str>>,
     pub parent_headers: Option<Vec<Arc<str>>>,
     pub rng: StringRng,
+    pub loader: Option<Loader<'a>>,
+    /// Warnings, eg. about unset variables
+    pub warnings: Vec<LibWarning>,
+    /// Highlighter of lines within a section of code
+    pub highlighter: Option<Highlighter>,
+}
+
+/// Resolves URIs of `LOAD` instructions to their content
+#[derive(Clone, Copy)]
+pub struct Loader<'a>(pub &'a dyn Fn(&str) -> Option<String>);
+
+impl std::fmt::Debug for Loader<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Loader")
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -108,8 +139,11 @@ impl<'a> Context<'a> {
     }
 }
 
+/// Parsed lang name, parent lang name, media type and rules
+type ParsedLang = (String, Option<String>, String, RuleMap);
+
 /// Parse input to rules
-pub fn parse(input: &str) -> Result<(String, String, RuleMap), LibError> {
+pub fn parse(input: &str) -> Result<ParsedLang, LibError> {
     RawRuleParser::parse(Rule::Root, input)
         .map_err(|e| LibError::from(Box::new(e)))
         .and_then(parse_root)
@@ -117,8 +151,9 @@ pub fn parse(input: &str) -> Result<(String, String, RuleMap), LibError> {
 
 fn parse_root<'a>(
     mut pairs: impl Iterator<Item = Pair<'a, Rule>> + 'a,
-) -> Result<(String, String, RuleMap), LibError> {
+) -> Result<ParsedLang, LibError> {
     let mut name = String::new();
+    let mut extends = None;
     let mut media_type = String::new();
     let mut compile_rules = Vec::new();
     let mut preprocess_rules = Vec::new();
@@ -133,16 +168,23 @@ fn parse_root<'a>(
                 section => return Err(LibError::unknown_rule_section(section)),
             }
         } else if pair.as_rule() == Rule::Preamble {
-            let mut pairs = pair.into_inner();
-            name = pairs.next().unwrap().as_str().to_string();
-            media_type = pairs.next().unwrap().as_str().to_string();
+            for pair in pair.into_inner() {
+                match pair.as_rule() {
+                    Rule::Name => name = pair.as_str().to_string(),
+                    Rule::Parent => extends = Some(pair.as_str().to_string()),
+                    _ => media_type = pair.as_str().to_string(),
+                }
+            }
         }
     }
 
     let rules = HashMap::from([("COMPILE", compile_rules), ("PREPROCESS", preprocess_rules)]);
-    Ok((name, media_type, rules))
+    Ok((name, extends, media_type, rules))
 }
 
+static KNOWN_SETTINGS: &[&str] =
+    &["COMPOSABLE", "PARAGRAPHABLE", "UNESCAPED_VALUE", "OVERRIDING", "DROPPED", "HIGHLIGHTED"];
+
 /// Walk pairs
 fn parse_recursive<'a>(
     pairs: impl Iterator<Item = Pair<'a, Rule>>,
@@ -151,30 +193,52 @@ fn parse_recursive<'a>(
 
     static DEFAULT_SCHEMAS: OnceLock<Regex> = OnceLock::new();
     let re = DEFAULT_SCHEMAS.get_or_init(|| Regex::new(r"(\w+)-BY-(\w+)").unwrap());
+    // NOTE: props are space separated names in parentheses after the last header of a path,
+    // which regex groups of paths can not end with
+    static PATH_PROPS: OnceLock<Regex> = OnceLock::new();
+    let props_re = PATH_PROPS.get_or_init(|| Regex::new(r"\]\(([\w ]+)\)(\$?)$").unwrap());
 
     for pair in pairs {
         if pair.as_rule() == Rule::Rule {
+            let (line, _) = pair.line_col();
             let mut pairs = pair.into_inner().peekable();
-            let path = pairs.next().unwrap().as_str().to_string();
+            let path = pairs.next().unwrap().as_str();
+            let (path, props) = match props_re.captures(path) {
+                Some(c) => (
+                    format!("{}]{}", &path[..c.get(0).unwrap().start()], &c[2]),
+                    c[1].split_whitespace().map(String::from).collect(),
+                ),
+                None => (path.to_string(), Vec::new()),
+            };
+            let mut unknown_settings = Vec::new();
             let settings = match pairs.peek().unwrap().as_rule() {
                 Rule::Settings => {
                     let settings = pairs.next().unwrap().as_str();
                     let mut default_src = None;
                     let mut default_ref = None;
 
-                    re.captures_iter(settings).for_each(|c| {
-                        match (c.get(1).map(|k| k.as_str()), c.get(2).map(|v| v.as_str())) {
-                            (Some("SRC"), Some("EXEC")) => default_src = Some("exec"),
-                            (Some("SRC"), Some("COPY")) => default_src = Some("copy"),
-                            (Some("REF"), Some("COPY")) => default_ref = Some("copy"),
-                            (Some(key), Some(value)) => panic!("unknown default {key} {value}"),
-                            (_, _) => panic!("unknown default"),
+                    for c in re.captures_iter(settings) {
+                        match (&c[1], &c[2]) {
+                            ("SRC", "EXEC") => default_src = Some("exec"),
+                            ("SRC", "COPY") => default_src = Some("copy"),
+                            ("SRC", "INDEX") => default_src = Some("index"),
+                            ("REF", "COPY") => default_ref = Some("copy"),
+                            _ => return Err(LibError::unknown_rule_setting(&c[0])),
                         }
-                    });
+                    }
+                    unknown_settings = settings
+                        .trim_start_matches("IS ")
+                        .split_ascii_whitespace()
+                        .filter(|s| !KNOWN_SETTINGS.contains(s) && !re.is_match(s))
+                        .map(String::from)
+                        .collect();
                     LangSettings {
                         is_composable: settings.contains("COMPOSABLE"),
                         is_paragraphable: settings.contains("PARAGRAPHABLE"),
                         is_unescaped_value: settings.contains("UNESCAPED_VALUE"),
+                        is_overriding: settings.contains("OVERRIDING"),
+                        is_dropped: settings.contains("DROPPED"),
+                        is_highlighted: settings.contains("HIGHLIGHTED"),
                         default_src,
                         default_ref,
                     }
@@ -192,10 +256,23 @@ fn parse_recursive<'a>(
             let mut instructions = Vec::new();
             for mut pairs in pairs.map(Pair::into_inner) {
                 let op = pairs.next().unwrap().as_str().to_string();
-                let args = pairs.map(Arg::try_from).collect::<Result<_, _>>()?;
+                let args = pairs.map(Arg::try_from).collect::<Result<Vec<_>, _>>()?;
+                for arg in &args {
+                    if let Arg::Str(t) | Arg::File(t) | Arg::URIPath(t) = arg {
+                        template::tokenize(t)?;
+                    }
+                }
                 instructions.push(LangInstr { op, args });
             }
-            result.push(LangRule { path, regex, instructions, settings });
+            result.push(LangRule {
+                path,
+                regex,
+                instructions,
+                settings,
+                unknown_settings,
+                props,
+                line,
+            });
         }
     }
     Ok(result)
@@ -244,13 +321,95 @@ mod tests {
                 is_composable: true,
                 is_paragraphable: true,
                 is_unescaped_value: false,
+                is_overriding: false,
+                is_dropped: false,
+                is_highlighted: false,
                 default_src: Some("exec"),
                 default_ref: Some("copy"),
             },
+            unknown_settings: vec![],
+            props: vec![],
+            line: 3,
         };
         assert_eq!(rule.path, expected.path);
         assert_eq!(rule.instructions, expected.instructions);
         assert_eq!(rule.regex.as_str(), expected.regex.as_str());
         assert_eq!(rule.settings, expected.settings);
+        assert_eq!(rule.line, expected.line);
+    }
+
+    #[test]
+    fn test_parse_rule_props() {
+        let input = indoc! {
+            r#"
+            RULES FOR test PRODUCE text/plain
+            COMPILE RULES:
+            [...CODE...](id number)$
+              NOOP
+            "#
+        };
+        let lang = Lang::new(input).unwrap();
+
+        let rule = &lang.rules.get("COMPILE").unwrap()[0];
+        assert_eq!(rule.path, "[...CODE...]$");
+        assert_eq!(rule.props, ["id", "number"]);
+
+        let mut node = Node::default();
+        assert!(!rule.matches_node("[ CODE ]", &node));
+        node.add_prop("id", "x".into());
+        node.add_prop("number", "1".into());
+        assert!(rule.matches_node("[ CODE ]", &node));
+        assert!(!rule.matches_node("[ CODE ] [ SEC ]", &node));
+        assert!(!rule.matches_node("[ TABLE ]", &node));
+    }
+
+    #[test]
+    fn test_parse_rule_props_selectors() {
+        let input = indoc! {
+            r#"
+            RULES FOR test PRODUCE text/plain
+            COMPILE RULES:
+            [...CODE...](number)
+              NOOP
+
+            ^[...] ([ FIGURE ] )?[...IMAGE...]$
+              NOOP
+
+            [...](caption)$
+              NOOP
+            "#
+        };
+        let lang = Lang::new(input).unwrap();
+        let rules = lang.rules.get("COMPILE").unwrap();
+
+        // NOTE: props may end paths that are not anchored
+        assert_eq!(rules[0].path, "[...CODE...]");
+        assert_eq!(rules[0].props, ["number"]);
+        assert!(rules[0].matches("[ CODE ] [ SEC ]"));
+
+        // NOTE: groups are kept as part of a path
+        assert_eq!(rules[1].path, "^[...] ([ FIGURE ] )?[...IMAGE...]$");
+        assert!(rules[1].props.is_empty());
+
+        let mut node = Node::default();
+        assert!(rules[1].matches_node("[ ] [ IMAGE ]", &node));
+        assert!(!rules[2].matches_node("[ TABLE ]", &node));
+        node.add_prop("caption", "".into());
+        assert!(rules[2].matches_node("[ TABLE ]", &node));
+    }
+
+    #[test]
+    fn test_parse_unknown_default() {
+        let input = indoc! {
+            r#"
+            RULES FOR test PRODUCE text/plain
+            COMPILE RULES:
+            [rule...]
+              IS COMPOSABLE SRC-BY-MAGIC
+              NOOP
+            "#
+        };
+        let err = Lang::new(input).unwrap_err();
+        assert_eq!(err.to_string(), "unknown rule setting `SRC-BY-MAGIC`");
     }
 }
@@ -0,0 +1,266 @@
+use std::fmt::Write;
+use std::sync::OnceLock;
+
+use htmlize::{escape_attribute, escape_text};
+use regex::Regex;
+
+use crate::types::LibError;
+
+static MODIFIER_RE: OnceLock<Regex> = OnceLock::new();
+static MODIFIERS_RE: OnceLock<Regex> = OnceLock::new();
+
+/// Pattern of a chain of modifiers following a variable, eg. `:slug:d(none)`
+pub(crate) static MODIFIERS: &str = r"(?::(?:d\([^)]*\)|(?:slug|url|attr|json|pipe|j|u|l)\b))+";
+
+/// Part of a rule string template
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Token<'a> {
+    Text(&'a str),
+    /// Escaped character, eg. `\n`
+    Char(char),
+    /// Prop or stack variable with modifiers, eg. `$title:u` or `${title:u}`
+    Variable(&'a str, &'a str),
+    /// Builtin variable with modifiers, eg. `\v:slug`
+    Builtin(char, &'a str),
+}
+
+/// Split a template to tokens in a single pass
+pub(crate) fn tokenize(template: &str) -> Result<Vec<Token<'_>>, LibError> {
+    let invalid = |reason| LibError::invalid_template(template, reason);
+    let mut tokens = Vec::new();
+    let mut rest = template;
+
+    while !rest.is_empty() {
+        let text_len = rest.find(['\\', '$']).unwrap_or(rest.len());
+        if text_len > 0 {
+            tokens.push(Token::Text(&rest[..text_len]));
+            rest = &rest[text_len..];
+            continue;
+        }
+
+        let mut chars = rest.chars();
+        match (chars.next(), chars.next()) {
+            (Some('\\'), Some('"')) => tokens.push(Token::Char('"')),
+            (Some('\\'), Some('n')) => tokens.push(Token::Char('\n')),
+            (Some('\\'), Some(c @ ('r' | 'v' | 'i' | 'V' | 'm' | 'h' | 'H'))) => {
+                let modifiers = modifiers_prefix(&rest[2..]);
+                tokens.push(Token::Builtin(c, modifiers));
+                rest = &rest[2 + modifiers.len()..];
+                continue;
+            }
+            (Some('$'), Some('{')) => {
+                let end = rest.find('}').ok_or_else(|| invalid("unclosed `${`"))?;
+                let inner = &rest[2..end];
+                let name_len = name_len(inner);
+                if name_len == 0 {
+                    return Err(invalid("missing variable name"));
+                }
+                let (name, modifiers) = inner.split_at(name_len);
+                if modifiers_prefix(modifiers).len() != modifiers.len() {
+                    return Err(invalid("unknown modifier"));
+                }
+                tokens.push(Token::Variable(name, modifiers));
+                rest = &rest[end + 1..];
+                continue;
+            }
+            (Some('$'), Some(_)) if name_len(&rest[1..]) > 0 => {
+                let (name, tail) = rest[1..].split_at(name_len(&rest[1..]));
+                let modifiers = modifiers_prefix(tail);
+                tokens.push(Token::Variable(name, modifiers));
+                rest = &tail[modifiers.len()..];
+                continue;
+            }
+            // NOTE: other backslashes and dollar signs are kept as is
+            (Some(c), _) => {
+                tokens.push(Token::Text(&rest[..c.len_utf8()]));
+                rest = &rest[c.len_utf8()..];
+                continue;
+            }
+            (None, _) => unreachable!(),
+        }
+        rest = &rest[2..];
+    }
+    Ok(tokens)
+}
+
+/// Get length of a variable name, which may contain dashes but not start or end with one
+fn name_len(input: &str) -> usize {
+    if input.starts_with('-') {
+        return 0;
+    }
+    let len = input
+        .find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '-'))
+        .unwrap_or(input.len());
+    input[..len].trim_end_matches('-').len()
+}
+
+fn modifiers_prefix(input: &str) -> &str {
+    let re = MODIFIERS_RE.get_or_init(|| Regex::new(&format!("^{MODIFIERS}")).unwrap());
+    re.find(input).map(|m| m.as_str()).unwrap_or_default()
+}
+
+/// Check if modifiers give a default for a missing value
+pub(crate) fn has_default(modifiers: &str) -> bool {
+    parse(modifiers).any(|(name, _)| name == "d")
+}
+
+/// Check if modifiers escape a value, in which case it should not be escaped by default
+pub(crate) fn is_escaping(modifiers: &str) -> bool {
+    parse(modifiers).any(|(name, _)| ["url", "attr", "json"].contains(&name))
+}
+
+/// Check if modifiers join a stack instead of taking its last value
+pub(crate) fn is_joining(modifiers: &str) -> bool {
+    parse(modifiers).any(|(name, _)| name == "j")
+}
+
+fn parse(modifiers: &str) -> impl Iterator<Item = (&str, Option<&str>)> {
+    let re = MODIFIER_RE.get_or_init(|| Regex::new(r":(?:d\(([^)]*)\)|(\w+))").unwrap());
+    re.captures_iter(modifiers).map(|c| match (c.get(1), c.get(2)) {
+        (Some(default), _) => ("d", Some(default.as_str())),
+        (_, Some(name)) => (name.as_str(), None),
+        _ => unreachable!(),
+    })
+}
+
+/// Apply modifiers in order to a value, where a missing value is empty unless defaulted
+pub(crate) fn apply(value: Option<&str>, modifiers: &str) -> String {
+    let mut value = value.unwrap_or_default().to_string();
+    for (name, arg) in parse(modifiers) {
+        value = match name {
+            "d" if value.is_empty() => arg.unwrap_or_default().to_string(),
+            "u" => value.to_uppercase(),
+            "l" => value.to_lowercase(),
+            "slug" => slug(&value),
+            "url" => url_encode(&value),
+            "attr" => escape_attribute(&value).into_owned(),
+            "json" => json_escape(&value),
+            "pipe" => value.replace('|', "\\|"),
+            _ => value,
+        };
+    }
+    value
+}
+
+/// Escape a value for HTML text unless modifiers already escape it
+pub(crate) fn escape_default(value: &str, modifiers: &str) -> String {
+    match is_escaping(modifiers) {
+        true => value.to_string(),
+        false => escape_text(value).into_owned(),
+    }
+}
+
+/// Convert to lowercase words separated by dashes, eg. for anchor ids
+pub(crate) fn slug(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    for c in value.chars().flat_map(char::to_lowercase) {
+        match c.is_alphanumeric() {
+            true => result.push(c),
+            false if !result.is_empty() && !result.ends_with('-') => result.push('-'),
+            false => {}
+        }
+    }
+    result.trim_end_matches('-').to_string()
+}
+
+/// Percent-encode all but unreserved URL characters
+fn url_encode(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                result.push(byte as char)
+            }
+            _ => write!(&mut result, "%{byte:02X}").expect("write"),
+        }
+    }
+    result
+}
+
+/// Escape a value for use inside a JSON string
+fn json_escape(value: &str) -> String {
+    let quoted = serde_json::to_string(value).expect("serializable string");
+    quoted[1..quoted.len() - 1].to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_apply() {
+        assert_eq!(apply(Some("Hello World"), ":u"), "HELLO WORLD");
+        assert_eq!(apply(Some("Hello World"), ":l"), "hello world");
+        assert_eq!(apply(Some(" Hello, World! 2"), ":slug"), "hello-world-2");
+        assert_eq!(apply(Some("a b&c/ö"), ":url"), "a%20b%26c%2F%C3%B6");
+        assert_eq!(
+            apply(Some(r#"say "hi" & <bye>"#), ":attr"),
+            "say &quot;hi&quot; &amp; &lt;bye&gt;"
+        );
+        assert_eq!(apply(Some("a \"b\"\n\\"), ":json"), r#"a \"b\"\n\\"#);
+        assert_eq!(apply(Some("a | b"), ":pipe"), r"a \| b");
+        assert_eq!(apply(None, ":d(Untitled)"), "Untitled");
+        assert_eq!(apply(Some(""), ":d(Untitled):u"), "UNTITLED");
+        assert_eq!(apply(Some("Title"), ":d(Untitled)"), "Title");
+    }
+
+    #[test]
+    fn test_tokenize() {
+        use Token::*;
+        assert_eq!(
+            tokenize(r#"<a id=\"$tab-\i\">${tabid:u}$tab:slug\v:attr</a>\n$ 5\t"#).unwrap(),
+            vec![
+                Text("<a id="),
+                Char('"'),
+                Variable("tab", ""),
+                Text("-"),
+                Builtin('i', ""),
+                Char('"'),
+                Text(">"),
+                Variable("tabid", ":u"),
+                Variable("tab", ":slug"),
+                Builtin('v', ":attr"),
+                Text("</a>"),
+                Char('\n'),
+                Text("$"),
+                Text(" 5"),
+                Text("\\"),
+                Text("t"),
+            ]
+        );
+        assert_eq!(tokenize("$a:b").unwrap(), vec![Variable("a", ""), Text(":b")]);
+        assert_eq!(
+            tokenize("$data-id ${data-id:u}-$a-").unwrap(),
+            vec![
+                Variable("data-id", ""),
+                Text(" "),
+                Variable("data-id", ":u"),
+                Text("-"),
+                Variable("a", ""),
+                Text("-"),
+            ]
+        );
+        assert_eq!(tokenize("$-a").unwrap(), vec![Text("$"), Text("-a")]);
+        assert_eq!(tokenize("").unwrap(), vec![]);
+
+        let error = |template| tokenize(template).unwrap_err().to_string();
+        assert_eq!(error("${title"), "invalid template `${title`: unclosed `${`");
+        assert_eq!(error("${}"), "invalid template `${}`: missing variable name");
+        assert_eq!(error("${title:x}"), "invalid template `${title:x}`: unknown modifier");
+    }
+
+    #[test]
+    fn test_modifiers() {
+        let re = Regex::new(&format!(r"\$(\w+)({MODIFIERS})")).unwrap();
+        let caps = re.captures("$title:slug:d(none) $user:$users:u").unwrap();
+        assert_eq!(&caps[2], ":slug:d(none)");
+        assert!(!re.is_match("$user:ser"));
+        assert!(is_escaping(":u:attr"));
+        assert!(is_joining(":j:u"));
+        assert!(has_default(":u:d()"));
+        assert_eq!(escape_default("<b>", ":u"), "&lt;b&gt;");
+        assert_eq!(escape_default("a b", ":url"), "a b");
+    }
+}
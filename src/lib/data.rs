@@ -0,0 +1,205 @@
+use std::{path::Path, sync::Arc};
+
+use serde_json::Value;
+
+use crate::{
+    ast::{Node, NodeBuilder},
+    parser::Rule,
+    source,
+    types::LibError,
+};
+
+/// Field of a data record, keyed if it comes from an object
+pub type Field = (Option<Arc<str>>, Arc<str>);
+
+static EXTENSIONS: &[&str] = &["csv", "json", "toml"];
+
+/// Check if a path is a data file by its extension
+pub fn is_data(path: &str) -> bool {
+    let ext = Path::new(path).extension().and_then(|e| e.to_str());
+    ext.is_some_and(|ext| EXTENSIONS.contains(&ext))
+}
+
+/// Parse a CSV, JSON or TOML data file to records
+///
+/// The records are kept under a `DATA` section, so that includes may convert them to tables or
+/// lists once loaded. Sections have headers, so that records are not built to paragraphs. The
+/// file is also kept as a `SOURCE` section, so that it can be included verbatim.
+pub fn parse(input: &str, path: &str) -> Result<Node, LibError> {
+    let ext = Path::new(path).extension().and_then(|e| e.to_str());
+    let records = match ext {
+        Some("csv") => parse_csv(input).map_err(|e| LibError::invalid_data(path, e.to_string()))?,
+        Some("json") => {
+            let value = serde_json::from_str(input);
+            value_records(value.map_err(|e| LibError::invalid_data(path, e.to_string()))?)
+        }
+        Some("toml") => {
+            let table = input.parse::<toml::Table>();
+            let table = table.map_err(|e| LibError::invalid_data(path, e.to_string()))?;
+            value_records(toml_value(toml::Value::Table(table)))
+        }
+        _ => return Err(LibError::invalid_data(path, "unknown data format".to_string())),
+    };
+
+    let records = records.into_iter().map(|fields| {
+        let fields = fields.into_iter().map(|(key, value)| {
+            let mut line = Node::line(&value);
+            if let Some(key) = key {
+                line.add_prop("key", key);
+            }
+            line
+        });
+        let section = NodeBuilder::new(Rule::Section)
+            .headers(Some(vec![Arc::from("FIELDS")]))
+            .add_children(fields)
+            .done();
+        NodeBuilder::new(Rule::Block)
+            .headers(Some(vec![Arc::from("RECORD")]))
+            .add_child(section)
+            .done()
+    });
+    let section = NodeBuilder::new(Rule::Section)
+        .headers(Some(vec![Arc::from("DATA")]))
+        .add_children(records)
+        .done();
+    Ok(NodeBuilder::root().add_child(section).add_child(source::section(input)).done())
+}
+
+/// Get records of a parsed data file, if the node is one
+pub fn records(node: &Node) -> Option<Vec<Vec<Field>>> {
+    let section = node.children.as_ref()?.first()?;
+    if section.headers.as_deref() != Some(&[Arc::from("DATA")]) {
+        return None;
+    }
+    let records = section.children.iter().flatten().map(|record| {
+        let fields = record.children.iter().flatten().flat_map(|s| s.children.iter().flatten());
+        fields
+            .map(|field| (field.find_prop("key"), field.value.clone().unwrap_or_default()))
+            .collect()
+    });
+    Some(records.collect())
+}
+
+fn parse_csv(input: &str) -> Result<Vec<Vec<Field>>, csv::Error> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(input.as_bytes());
+    reader
+        .records()
+        .map(|record| Ok(record?.iter().map(|v| (None, Arc::from(v))).collect()))
+        .collect()
+}
+
+/// Convert a JSON value to records
+///
+/// Arrays become one record per item, where objects have keyed fields. Other objects become
+/// records of keys and values, unless they only wrap a single array (eg. `[[release]]` in TOML).
+fn value_records(value: Value) -> Vec<Vec<Field>> {
+    let field = |key: Option<&str>, value: Value| (key.map(Arc::from), Arc::from(scalar(value)));
+    match value {
+        Value::Object(map) if map.len() == 1 && map.values().all(Value::is_array) => {
+            value_records(map.into_iter().next().expect("one entry").1)
+        }
+        Value::Object(map) => map
+            .into_iter()
+            .map(|(key, value)| {
+                vec![field(Some("key"), Value::String(key)), field(Some("value"), value)]
+            })
+            .collect(),
+        Value::Array(items) => items
+            .into_iter()
+            .map(|item| match item {
+                Value::Object(map) => map.into_iter().map(|(k, v)| field(Some(&k), v)).collect(),
+                Value::Array(values) => values.into_iter().map(|v| field(None, v)).collect(),
+                other => vec![field(None, other)],
+            })
+            .collect(),
+        other => vec![vec![field(None, other)]],
+    }
+}
+
+/// Convert a scalar to text, while nested values are kept as JSON
+fn scalar(value: Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s,
+        other => other.to_string(),
+    }
+}
+
+fn toml_value(value: toml::Value) -> Value {
+    match value {
+        toml::Value::String(s) => Value::String(s),
+        toml::Value::Integer(i) => Value::from(i),
+        toml::Value::Float(f) => Value::from(f),
+        toml::Value::Boolean(b) => Value::Bool(b),
+        toml::Value::Datetime(d) => Value::String(d.to_string()),
+        toml::Value::Array(values) => Value::Array(values.into_iter().map(toml_value).collect()),
+        toml::Value::Table(table) => {
+            Value::Object(table.into_iter().map(|(k, v)| (k, toml_value(v))).collect())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn values(node: &Node) -> Vec<Vec<(Option<String>, String)>> {
+        let records = records(node).unwrap().into_iter();
+        records
+            .map(|fields| {
+                let fields = fields.into_iter();
+                fields.map(|(k, v)| (k.map(|k| k.to_string()), v.to_string())).collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_csv() {
+        let node = parse("name,size\n\"Ant, small\",1\n", "a.csv").unwrap();
+        let field = |v: &str| (None, v.to_string());
+        assert_eq!(
+            values(&node),
+            [[field("name"), field("size")], [field("Ant, small"), field("1")]]
+        );
+    }
+
+    #[test]
+    fn test_parse_json_objects() {
+        let node = parse(r#"[{"name": "Ant", "tags": ["a"]}, {"size": null}]"#, "a.json");
+        let field = |k: &str, v: &str| (Some(k.to_string()), v.to_string());
+        assert_eq!(
+            values(&node.unwrap()),
+            [vec![field("name", "Ant"), field("tags", r#"["a"]"#)], vec![field("size", "")]]
+        );
+    }
+
+    #[test]
+    fn test_parse_toml_unwraps_array_of_tables() {
+        let input = "[[release]]\nversion = \"1.0\"\n[[release]]\nversion = \"1.1\"\n";
+        let node = parse(input, "a.toml").unwrap();
+        let field = |v: &str| (Some("version".to_string()), v.to_string());
+        assert_eq!(values(&node), [[field("1.0")], [field("1.1")]]);
+    }
+
+    #[test]
+    fn test_parse_toml_to_keys_and_values() {
+        let node = parse("a = 1\nb = true\n", "a.toml").unwrap();
+        let field = |k: &str, v: &str| (Some(k.to_string()), v.to_string());
+        assert_eq!(
+            values(&node),
+            [[field("key", "a"), field("value", "1")], [field("key", "b"), field("value", "true")]]
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(parse("{", "a.json").is_err());
+        assert!(is_data("a/b.csv"));
+        assert!(!is_data("a/b.md"));
+    }
+}
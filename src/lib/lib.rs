@@ -1,5 +1,7 @@
 pub mod ast;
 pub mod compiler;
+pub mod data;
 pub mod parser;
 pub mod preprocessor;
+pub mod source;
 pub mod types;
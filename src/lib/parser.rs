@@ -98,6 +98,7 @@ fn take_headers(pairs: &mut Peekable<Pairs<'_, Rule>>) -> Option<Vec<Arc<str>>>
         .map(|p| p.split_ascii_whitespace().map(Arc::from).collect())
 }
 
+#[allow(clippy::result_large_err)]
 fn take_props(pairs: &mut Peekable<Pairs<'_, Rule>>) -> Result<Option<Props>, ()> {
     pairs
         .next_if(|p| matches!(p.as_rule(), Rule::BLOCK_PROPS))
@@ -1,15 +1,27 @@
 use std::collections::HashSet;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 
 use hashbrown::hash_map::Entry;
+use hashbrown::HashMap;
+use mime2ext::mime2ext;
+use regex::Regex;
 
-use crate::ast::{Node, NodeBuilder};
+use crate::ast::{Node, NodeBuilder, Props};
 use crate::compiler::lang::Lang;
 use crate::compiler::rule::{Context, LangSettings};
+use crate::compiler::template::slug;
+use crate::data;
 use crate::parser::Rule;
-use crate::types::{AstMap, Dependency, LibError, LocationMap, Pointer, URI};
+use crate::source;
+use crate::types::{AstMap, Dependency, LibError, LibWarning, LocationMap, Pointer, URI};
 
 static PREPROCESSABLE_PROPS: &[&str] = &["src", "ref"];
+static INDEXABLE_EXTENSION: &str = ".md";
+static INDEX_IGNORED_PROPS: &[&str] = &["src", "ref", "id", "href", "tangle"];
+/// Headers of blocks numbered per kind when referenced or captioned, along with their labels
+static NUMBERED_KINDS: &[(&str, &str)] =
+    &[("FIGURE", "Figure"), ("CODE", "Listing"), ("TABLE", "Table")];
 
 /// Preprocess AST
 pub fn preprocess(
@@ -39,6 +51,305 @@ pub fn preprocess(
     Ok((deps, new_asts))
 }
 
+/// Add anchors to headings and fill tables of contents with links to them
+///
+/// Runs after includes have been resolved, so headings of included documents are collected too.
+pub fn preprocess_headings(node: &mut Node) {
+    let mut headings = Vec::new();
+    let mut used = HashSet::new();
+    collect_headings(node, 0, &mut headings, &mut used, &mut HashSet::new());
+    fill_tocs(node, &headings, &mut HashSet::new());
+}
+
+/// Heading level, title and anchor
+type Heading = (usize, String, Arc<str>);
+
+/// Collect headings in document order and get the first anchor added within a node
+fn collect_headings(
+    node: &mut Node,
+    depth: usize,
+    headings: &mut Vec<Heading>,
+    used: &mut HashSet<String>,
+    visited: &mut HashSet<usize>,
+) -> Option<Arc<str>> {
+    let is_heading = node.rule == Rule::Block && has_header(node, "HEADING");
+    let depth = match node.rule {
+        Rule::Block if is_heading => depth + 1,
+        Rule::Block | Rule::RootA | Rule::RootB => 0,
+        _ => depth,
+    };
+
+    let mut first = None;
+    if is_heading {
+        let title = block_text(node);
+        if !title.is_empty() {
+            let anchor = unique_anchor(&title, used);
+            headings.push((depth, title, anchor.clone()));
+            first = Some(anchor);
+        }
+    }
+
+    if let Some(Pointer(weak)) = &node.pointer {
+        // NOTE: each included node is only visited once
+        if let Some(arc) = weak.upgrade() {
+            if visited.insert(Arc::as_ptr(&arc) as usize) {
+                let mut node = arc.lock().expect("poisoned lock");
+                let anchor = collect_headings(&mut node, depth, headings, used, visited);
+                first = first.or(anchor);
+            }
+        }
+    }
+    for child in node.children.iter_mut().flatten() {
+        let anchor = collect_headings(child, depth, headings, used, visited);
+        first = first.or(anchor);
+    }
+
+    // NOTE: outer blocks of nested headings share the anchor of the heading they wrap
+    if !is_heading {
+        return if node.rule == Rule::Section { first } else { None };
+    }
+    if let Some(anchor) = &first {
+        node.set_prop("anchor", anchor.clone());
+    }
+    first
+}
+
+fn has_header(node: &Node, header: &str) -> bool {
+    node.headers.iter().flatten().any(|h| &**h == header)
+}
+
+/// Join lines directly within sections of a block
+fn block_text(node: &Node) -> String {
+    let sections = node.children.iter().flatten().filter(|n| n.rule == Rule::Section);
+    let values = sections
+        .flat_map(|s| s.children.iter().flatten())
+        .filter(|n| matches!(n.rule, Rule::Line | Rule::Paragraph))
+        .filter_map(|n| n.value.as_deref())
+        .filter(|v| !v.is_empty())
+        .collect::<Vec<_>>();
+    values.join(" ")
+}
+
+/// Build an anchor from a title, suffixed with a number if already used
+fn unique_anchor(title: &str, used: &mut HashSet<String>) -> Arc<str> {
+    let base = match slug(title) {
+        slug if slug.is_empty() => "section".to_string(),
+        slug => slug,
+    };
+    let mut anchor = base.clone();
+    let mut count = 0;
+    while !used.insert(anchor.clone()) {
+        count += 1;
+        anchor = format!("{base}-{count}");
+    }
+    Arc::from(anchor)
+}
+
+/// Replace contents of TOC blocks with nested lists of links to headings
+fn fill_tocs(node: &mut Node, headings: &[Heading], visited: &mut HashSet<usize>) {
+    if node.rule == Rule::Block && has_header(node, "TOC") {
+        let depth = node.find_prop("depth").and_then(|d| d.parse().ok());
+        let headings = match depth {
+            Some(depth) => headings.iter().filter(|(level, ..)| *level <= depth).cloned().collect(),
+            None => headings.to_vec(),
+        };
+        let section = NodeBuilder::new(Rule::Section).add_children([toc_list(&headings)]).done();
+        node.children = Some(vec![section]);
+        return;
+    }
+
+    if let Some(Pointer(weak)) = &node.pointer {
+        if let Some(arc) = weak.upgrade() {
+            if visited.insert(Arc::as_ptr(&arc) as usize) {
+                fill_tocs(&mut arc.lock().expect("poisoned lock"), headings, visited);
+            }
+        }
+    }
+    for child in node.children.iter_mut().flatten() {
+        fill_tocs(child, headings, visited);
+    }
+}
+
+/// Build a list of links where deeper headings are nested in lists following their parent
+fn toc_list(headings: &[Heading]) -> Node {
+    let mut items = Vec::new();
+    let mut rest = headings;
+    while let Some(((level, title, anchor), tail)) = rest.split_first() {
+        let len = tail.iter().take_while(|(l, ..)| l > level).count();
+        items.push(
+            NodeBuilder::block(">")
+                .headers(Some(vec![Arc::from("LINK")]))
+                .add_prop((Arc::from("href"), Arc::from(format!("#{anchor}"))))
+                .add_section(vec![Node::line(title)])
+                .done(),
+        );
+        if len > 0 {
+            items.push(toc_list(&tail[..len]));
+        }
+        rest = &tail[len..];
+    }
+    NodeBuilder::block("*")
+        .headers(Some(vec![Arc::from("LIST")]))
+        .add_section(items)
+        .done()
+}
+
+/// Number referenced or captioned blocks per kind and fill cross-references to them with labels
+///
+/// Nodes moved to asts by `preprocess_ids` keep their id, so included targets are numbered once.
+pub fn preprocess_xrefs(node: &mut Node) -> Vec<LibWarning> {
+    let mut targets = HashSet::new();
+    let mut numbers = HashMap::new();
+    let mut counts = HashMap::new();
+    let mut warnings = Vec::new();
+    collect_xref_targets(node, &mut targets, &mut HashSet::new());
+    number_targets(node, &targets, &mut numbers, &mut counts, &mut HashSet::new());
+    fill_xrefs(node, &numbers, &mut HashSet::new(), &mut warnings);
+    warnings
+}
+
+/// Kind label and number of a numbered block
+type Numbered = (&'static str, usize);
+
+/// Get the target id of an XREF block, or of a LINK block to a fragment
+fn xref_target(node: &Node) -> Option<Arc<str>> {
+    match node.rule {
+        Rule::Block if has_header(node, "XREF") => node.find_prop("to"),
+        Rule::Block if has_header(node, "LINK") => {
+            node.find_prop("href").filter(|href| href.starts_with('#'))
+        }
+        _ => None,
+    }
+}
+
+/// Collect ids referenced by XREF and LINK blocks
+fn collect_xref_targets(
+    node: &Node,
+    targets: &mut HashSet<Arc<str>>,
+    visited: &mut HashSet<usize>,
+) {
+    if let Some(target) = xref_target(node) {
+        targets.insert(Arc::from(target.trim_start_matches('#')));
+    }
+
+    if let Some(Pointer(weak)) = &node.pointer {
+        if let Some(arc) = weak.upgrade() {
+            if visited.insert(Arc::as_ptr(&arc) as usize) {
+                collect_xref_targets(&arc.lock().expect("poisoned lock"), targets, visited);
+            }
+        }
+    }
+    for child in node.children.iter().flatten() {
+        collect_xref_targets(child, targets, visited);
+    }
+}
+
+fn number_targets(
+    node: &mut Node,
+    targets: &HashSet<Arc<str>>,
+    numbers: &mut HashMap<Arc<str>, Numbered>,
+    counts: &mut HashMap<&'static str, usize>,
+    visited: &mut HashSet<usize>,
+) {
+    let kind = NUMBERED_KINDS.iter().find(|(header, _)| has_header(node, header));
+    let id = node.find_prop("id");
+    let caption = node.find_prop("caption");
+    let is_target = id.as_ref().is_some_and(|id| targets.contains(id));
+    if let (Rule::Block, Some((_, kind)), true) = (node.rule, kind, is_target || caption.is_some())
+    {
+        let mut next = || {
+            let count = counts.entry(kind).or_default();
+            *count += 1;
+            (*kind, *count)
+        };
+        let (_, number) = match id {
+            Some(id) => *numbers.entry(id).or_insert_with(next),
+            None => next(),
+        };
+        let label = match caption.filter(|c| !c.is_empty()) {
+            Some(caption) => format!("{kind} {number}: {caption}"),
+            None => format!("{kind} {number}"),
+        };
+        node.set_prop("number", Arc::from(number.to_string()));
+        node.set_prop("label", Arc::from(label.as_str()));
+        caption_table(node, &label);
+    }
+
+    if let Some(Pointer(weak)) = &node.pointer {
+        if let Some(arc) = weak.upgrade() {
+            if visited.insert(Arc::as_ptr(&arc) as usize) {
+                let mut node = arc.lock().expect("poisoned lock");
+                number_targets(&mut node, targets, numbers, counts, visited);
+            }
+        }
+    }
+    for child in node.children.iter_mut().flatten() {
+        number_targets(child, targets, numbers, counts, visited);
+    }
+}
+
+/// Replace the caption of a filled table with a label, or add one
+fn caption_table(node: &mut Node, label: &str) {
+    if !has_header(node, "TABLE") {
+        return;
+    }
+    let Some(sections) = node.children.as_mut() else {
+        return;
+    };
+    if !sections.iter().any(|s| ["HEAD", "STUB", "BODY"].iter().any(|h| has_header(s, h))) {
+        return;
+    }
+    sections.retain(|s| !has_header(s, "CAPTION"));
+    let caption = NodeBuilder::new(Rule::Section)
+        .headers(Some(vec![Arc::from("CAPTION")]))
+        .add_children([Node::line(label)])
+        .done();
+    sections.insert(0, caption);
+}
+
+/// Turn XREF blocks to links and fill empty links to numbered blocks with labels
+fn fill_xrefs(
+    node: &mut Node,
+    numbers: &HashMap<Arc<str>, Numbered>,
+    visited: &mut HashSet<usize>,
+    warnings: &mut Vec<LibWarning>,
+) {
+    let is_xref = node.rule == Rule::Block && has_header(node, "XREF");
+    let target = xref_target(node);
+
+    if let Some(target) = target.filter(|_| is_xref || block_text(node).is_empty()) {
+        let id = target.trim_start_matches('#');
+        let label = match numbers.get(id) {
+            Some((kind, number)) => format!("{kind} {number}"),
+            None if is_xref => {
+                warnings.push(LibWarning::UnknownXref(id.to_string()));
+                target.to_string()
+            }
+            None => return,
+        };
+        node.set_prop("href", Arc::from(format!("#{id}")));
+        let headers = node.headers.get_or_insert_with(Default::default);
+        let header = Arc::from("LINK");
+        if !headers.contains(&header) {
+            headers.push(header);
+        }
+        let section = NodeBuilder::new(Rule::Section).add_children([Node::line(&label)]).done();
+        node.children = Some(vec![section]);
+        return;
+    }
+
+    if let Some(Pointer(weak)) = &node.pointer {
+        if let Some(arc) = weak.upgrade() {
+            if visited.insert(Arc::as_ptr(&arc) as usize) {
+                fill_xrefs(&mut arc.lock().expect("poisoned lock"), numbers, visited, warnings);
+            }
+        }
+    }
+    for child in node.children.iter_mut().flatten() {
+        fill_xrefs(child, numbers, visited, warnings);
+    }
+}
+
 #[allow(clippy::too_many_arguments)]
 fn preprocess_recursive<'a>(
     node: &mut Node,
@@ -52,9 +363,14 @@ fn preprocess_recursive<'a>(
     lang: &'a Lang,
     base_path: &str,
 ) -> Result<(), LibError> {
+    // NOTE: root headers and props are added before rules are matched
+    if let Rule::RootA | Rule::RootB = node.rule {
+        preprocess_headers(node, headers);
+    }
+
     let path = node.build_path(base_path);
     ctx.set_parent(node);
-    let mut rules = lang.get_rules("PREPROCESS", &path).peekable();
+    let mut rules = lang.get_rules("PREPROCESS", &path, node).peekable();
     let mut rules_stack = Vec::new();
     let mut merged_settings = rules.peek().map(|r| r.settings).unwrap_or_default();
 
@@ -69,25 +385,24 @@ fn preprocess_recursive<'a>(
 
     match node.rule {
         Rule::RootA | Rule::RootB => {
-            preprocess_headers(node, headers);
-            preprocess_includes(node, asts, locs, context, deps, &settings);
+            preprocess_includes(node, asts, locs, context, deps, &settings, lang);
         }
         Rule::Block => {
             preprocess_headers(node, None);
-            preprocess_includes(node, asts, locs, context, deps, &settings);
+            preprocess_table(node);
+            preprocess_lists(node);
+            preprocess_includes(node, asts, locs, context, deps, &settings, lang);
         }
         Rule::Section => {
             preprocess_paragraphs(node, &settings);
-            preprocess_includes(node, asts, locs, context, deps, &settings);
+            preprocess_includes(node, asts, locs, context, deps, &settings, lang);
         }
         _ => {}
     }
 
     // NOTE: preprocess children after path may have changed (eg. new headers)
-    if node.children.is_some() {
-        let path = node.build_path(base_path);
-
-        let children = node.children.as_mut().expect("is some");
+    let path = node.build_path(base_path);
+    if let Some(children) = node.children.as_mut() {
         for child in children.iter_mut() {
             preprocess_recursive(
                 child, headers, ctx, asts, locs, context, deps, new_asts, lang, &path,
@@ -115,6 +430,203 @@ fn preprocess_recursive<'a>(
     Ok(())
 }
 
+/// Convert tables and lists including data files to rows and items
+///
+/// Runs after includes have been loaded, since data files are parsed to records of their own.
+/// Columns are selected by a `columns` prop of comma separated names, or of 1-based indices if
+/// the data has no names. The `header` prop sets the count of header rows, which defaults to 1.
+pub fn preprocess_data(node: &mut Node) -> Vec<LibWarning> {
+    let mut warnings = Vec::new();
+    preprocess_data_recursive(node, &mut HashSet::new(), &mut warnings);
+    warnings
+}
+
+fn preprocess_data_recursive(
+    node: &mut Node,
+    visited: &mut HashSet<usize>,
+    warnings: &mut Vec<LibWarning>,
+) {
+    let is_table = has_header(node, "TABLE");
+    if node.rule == Rule::Block && (is_table || has_header(node, "LIST")) {
+        if let Some(records) = find_include(node, data::records) {
+            let (names, rows) = data_rows(node, records, warnings);
+            fill_data(node, names, rows, is_table);
+            return;
+        }
+    }
+
+    if let Some(Pointer(weak)) = &node.pointer {
+        if let Some(arc) = weak.upgrade() {
+            if visited.insert(Arc::as_ptr(&arc) as usize) {
+                let mut node = arc.lock().expect("poisoned lock");
+                preprocess_data_recursive(&mut node, visited, warnings);
+            }
+        }
+    }
+    for child in node.children.iter_mut().flatten() {
+        preprocess_data_recursive(child, visited, warnings);
+    }
+}
+
+/// Copy selected lines of included source files to blocks
+///
+/// Runs after includes have been loaded, like [`preprocess_data`]. Lines are selected by `lines`
+/// and `region` props, which includes get from fragments like `#L10-L42` and `#region=name`. A
+/// `dedent` prop removes common indentation, up to a width if it is a number, and a `trim` prop
+/// removes leading and trailing blank lines. Included Murkdown documents are copied as raw lines
+/// only to `CODE` blocks, or when lines are selected.
+pub fn preprocess_sources(node: &mut Node) -> Vec<LibWarning> {
+    let mut warnings = Vec::new();
+    preprocess_sources_recursive(node, &mut HashSet::new(), &mut warnings);
+    warnings
+}
+
+fn preprocess_sources_recursive(
+    node: &mut Node,
+    visited: &mut HashSet<usize>,
+    warnings: &mut Vec<LibWarning>,
+) {
+    if node.rule == Rule::Block {
+        let is_verbatim = has_header(node, "CODE")
+            || node.find_prop("lines").is_some()
+            || node.find_prop("region").is_some();
+        let lines = find_include(node, source::lines)
+            .or_else(|| is_verbatim.then(|| find_include(node, source::raw_lines)).flatten());
+        if let Some(lines) = lines {
+            fill_source(node, lines, warnings);
+            return;
+        }
+    }
+
+    if let Some(Pointer(weak)) = &node.pointer {
+        if let Some(arc) = weak.upgrade() {
+            if visited.insert(Arc::as_ptr(&arc) as usize) {
+                let mut node = arc.lock().expect("poisoned lock");
+                preprocess_sources_recursive(&mut node, visited, warnings);
+            }
+        }
+    }
+    for child in node.children.iter_mut().flatten() {
+        preprocess_sources_recursive(child, visited, warnings);
+    }
+}
+
+/// Replace contents of a block with selected lines of a source file
+fn fill_source(node: &mut Node, lines: Vec<Arc<str>>, warnings: &mut Vec<LibWarning>) {
+    let mut lines = match (node.find_prop("region"), node.find_prop("lines")) {
+        (Some(name), _) => source::select_region(&lines, &name).unwrap_or_else(|| {
+            warnings.push(LibWarning::UnknownRegion(name.to_string()));
+            Vec::new()
+        }),
+        (None, Some(range)) => source::select_lines(&lines, &range).to_vec(),
+        (None, None) => lines,
+    };
+    if node.find_prop("trim").is_some_and(|t| &*t != "false") {
+        source::trim(&mut lines);
+    }
+    if let Some(dedent) = node.find_prop("dedent").filter(|d| &**d != "false") {
+        source::dedent(&mut lines, dedent.parse().ok());
+    }
+
+    node.pointer = None;
+    let lines = lines.iter().map(|line| Node::line(line));
+    node.children = Some(vec![NodeBuilder::new(Rule::Section).add_children(lines).done()]);
+}
+
+/// Get a value of a node included by a block or its sections
+fn find_include<T>(node: &Node, get: impl Fn(&Node) -> Option<T>) -> Option<T> {
+    let sections = node.children.iter().flatten().filter(|n| n.rule == Rule::Section);
+    let mut pointers = node.pointer.iter().chain(sections.filter_map(|s| s.pointer.as_ref()));
+    pointers.find_map(|Pointer(weak)| get(&weak.upgrade()?.lock().expect("poisoned lock")))
+}
+
+/// Rows of data cells
+type Rows = Vec<Vec<Arc<str>>>;
+
+/// Get header and body rows of selected columns
+///
+/// Records with keyed fields get a header row of keys, in order of appearance.
+fn data_rows(
+    node: &Node,
+    records: Vec<Vec<data::Field>>,
+    warnings: &mut Vec<LibWarning>,
+) -> (Rows, Rows) {
+    let header = node.find_prop("header").and_then(|h| h.parse::<usize>().ok()).unwrap_or(1);
+    let mut keys = Vec::<Arc<str>>::new();
+    for key in records.iter().flatten().filter_map(|(key, _)| key.as_ref()) {
+        if !keys.contains(key) {
+            keys.push(key.clone());
+        }
+    }
+
+    let mut rows = match keys.is_empty() {
+        true => {
+            let values = records.into_iter().map(|fields| fields.into_iter().map(|(_, v)| v));
+            values.map(Iterator::collect).collect()
+        }
+        false => {
+            let values = records.iter().map(|fields| {
+                let value = |key| fields.iter().find(|(k, _)| k.as_ref() == Some(key));
+                let values = keys.iter().map(|key| value(key).map(|(_, v)| v.clone()));
+                values.map(Option::unwrap_or_default).collect()
+            });
+            let names = (header > 0).then(|| keys.clone());
+            names.into_iter().chain(values).collect::<Vec<Vec<_>>>()
+        }
+    };
+
+    let names = match keys.is_empty() {
+        true if header > 0 => rows.first().cloned().unwrap_or_default(),
+        true => Vec::new(),
+        false => keys,
+    };
+    if let Some(columns) = node.find_prop("columns") {
+        let columns = columns.split(',').map(str::trim).filter(|c| !c.is_empty());
+        let indices = columns
+            .filter_map(|column| {
+                let by_name = names.iter().position(|name| &**name == column);
+                let by_index = column.parse::<usize>().ok().and_then(|i| i.checked_sub(1));
+                let index = by_name.or(by_index);
+                if index.is_none() {
+                    warnings.push(LibWarning::UnknownColumn(column.to_string()));
+                }
+                index
+            })
+            .collect::<Vec<_>>();
+        for row in rows.iter_mut() {
+            *row = indices.iter().map(|&i| row.get(i).cloned().unwrap_or_default()).collect();
+        }
+    }
+
+    let body = rows.split_off(header.min(rows.len()));
+    (rows, body)
+}
+
+/// Replace contents of a table or list block with rows or items of data
+fn fill_data(node: &mut Node, head: Rows, body: Rows, is_table: bool) {
+    node.pointer = None;
+    if !is_table {
+        let separator = node.find_prop("separator").unwrap_or_else(|| Arc::from(": "));
+        let items = body.iter().map(|row| {
+            let values = row.iter().filter(|v| !v.is_empty()).map(|v| v.replace('\n', " "));
+            Node::line(&values.collect::<Vec<_>>().join(&separator))
+        });
+        node.children = Some(vec![NodeBuilder::new(Rule::Section).add_children(items).done()]);
+        preprocess_lists(node);
+        return;
+    }
+
+    let cells = |row: &Vec<Arc<str>>| row.iter().map(|v| v.replace('\n', " ")).collect::<Vec<_>>();
+    let columns = head.iter().chain(&body).map(Vec::len).max().unwrap_or_default();
+    let mut lines = head.iter().map(cells).collect::<Vec<_>>();
+    let separator = (!head.is_empty()).then(|| {
+        lines.push(vec!["---".to_string(); columns]);
+        lines.len() - 1
+    });
+    lines.extend(body.iter().map(cells));
+    fill_table(node, &lines, separator);
+}
+
 /// Adds implicit headers to nodes
 fn preprocess_headers(node: &mut Node, extra_headers: Option<&str>) {
     if let Some(extra) = extra_headers.map(str::to_uppercase) {
@@ -145,10 +657,261 @@ fn preprocess_headers(node: &mut Node, extra_headers: Option<&str>) {
                 headers.push(header);
             }
         }
+        Some("|") => {
+            let headers = node.headers.get_or_insert_with(Default::default);
+            let header = Arc::from("TABLE");
+            if !headers.contains(&header) {
+                headers.push(header);
+            }
+        }
+        Some("+") => {
+            let headers = node.headers.get_or_insert_with(Default::default);
+            for header in ["LIST", "ORDERED"].map(Arc::from) {
+                if !headers.contains(&header) {
+                    headers.push(header);
+                }
+            }
+        }
+        Some("-") => {
+            let kinds = ["LIST", "TASKS", "DEFINITIONS"];
+            if node.headers.iter().flatten().any(|h| kinds.contains(&&**h)) {
+                return;
+            }
+            let added: &[&str] = match () {
+                _ if item_values(node).any(|v| v.starts_with(": ")) => &["DEFINITIONS"],
+                _ if item_values(node).any(|v| task_mark(v).is_some()) => &["LIST", "TASKS"],
+                _ => &["LIST"],
+            };
+            let headers = node.headers.get_or_insert_with(Default::default);
+            headers.extend(added.iter().map(|&h| Arc::from(h)));
+        }
         _ => {}
     }
 }
 
+/// Get values of lines directly within sections of a block
+fn item_values(node: &Node) -> impl Iterator<Item = &str> {
+    let sections = node.children.iter().flatten().filter(|n| n.rule == Rule::Section);
+    sections
+        .flat_map(|s| s.children.iter().flatten())
+        .filter(|n| n.rule == Rule::Line)
+        .filter_map(|n| n.value.as_deref())
+}
+
+/// Get mark of a task item like `[x] Done`, along with the rest of the item
+fn task_mark(value: &str) -> Option<(&str, &str)> {
+    ["[ ] ", "[x] ", "[X] "]
+        .iter()
+        .find_map(|box_| value.strip_prefix(box_).map(|rest| (&box_[1..2], rest)))
+}
+
+/// Number items of ordered lists, split task boxes from items and pair definitions with terms
+fn preprocess_lists(node: &mut Node) {
+    let is_ordered = has_header(node, "ORDERED");
+    let is_tasks = has_header(node, "TASKS");
+    let is_definitions = has_header(node, "DEFINITIONS");
+    let start = node.find_prop("start").and_then(|s| s.parse::<i64>().ok()).unwrap_or(1);
+
+    let sections = node.children.iter_mut().flatten().filter(|n| n.rule == Rule::Section);
+    for section in sections {
+        let Some(children) = section.children.as_mut() else {
+            continue;
+        };
+        if is_definitions {
+            *children = std::mem::take(children).into_iter().map(definition_item).collect();
+            continue;
+        }
+
+        // NOTE: nested lists are not items themselves
+        let items = children.iter_mut().filter(|n| match n.rule {
+            Rule::Line => n.value.as_deref().is_some_and(|v| !v.is_empty()),
+            Rule::Block => {
+                let is_list = matches!(n.marker.as_deref(), Some("*" | "+" | "-"));
+                !is_list && !has_header(n, "LIST") && !has_header(n, "DEFINITIONS")
+            }
+            _ => false,
+        });
+        for (idx, item) in items.enumerate() {
+            if is_ordered {
+                item.set_prop("number", Arc::from((start + idx as i64).to_string()));
+            }
+            let task = item.value.as_deref().and_then(task_mark);
+            if let (true, Some((mark, rest))) = (is_tasks, task) {
+                let checked = if mark == " " { "" } else { "checked" };
+                let (mark, rest) = (Arc::from(mark), Arc::from(rest));
+                item.set_prop("checked", Arc::from(checked));
+                item.set_prop("mark", mark);
+                item.value = Some(rest);
+            }
+        }
+    }
+}
+
+/// Wrap a line of a definition list to a term or definition block
+fn definition_item(node: Node) -> Node {
+    let value = match (node.rule, node.value.as_deref()) {
+        (Rule::Line, Some(value)) if !value.is_empty() => value,
+        _ => return node,
+    };
+    let (header, value) = match value.strip_prefix(": ") {
+        Some(definition) => ("DEF", definition),
+        None => ("TERM", value),
+    };
+    NodeBuilder::new(Rule::Block)
+        .headers(Some(vec![Arc::from(header)]))
+        .add_section(vec![Node::line(value)])
+        .done()
+}
+
+/// Split lines of a table block to rows of cells
+///
+/// Lines of `|` blocks start after the marker, while other table blocks may start lines with a
+/// pipe too. Rows before a separator line like `| --- | :-: |` become header rows. Cells get an
+/// `align` prop from the separator or the `align` prop of the table, along with `lpad` and `rpad`
+/// props that align columns in plain text. Tables without a header get a `STUB` section of an empty
+/// row and a separator row instead.
+fn preprocess_table(node: &mut Node) {
+    if !has_header(node, "TABLE") || node.children.is_none() {
+        return;
+    }
+    let is_marked = node.marker.as_deref() == Some("|");
+    // NOTE: the table is already split if it is preprocessed again
+    let sections = node.children.iter().flatten();
+    if sections.clone().any(|s| s.headers.is_some()) {
+        return;
+    }
+
+    let lines = sections
+        .flat_map(|s| s.children.iter().flatten())
+        .filter(|n| n.rule == Rule::Line)
+        .filter_map(|n| n.value.as_deref())
+        .filter(|v| !v.trim().is_empty())
+        .map(|v| match is_marked {
+            true => split_cells(v),
+            false => split_cells(v.trim_start().strip_prefix('|').unwrap_or(v)),
+        })
+        .collect::<Vec<_>>();
+    let separator = lines.iter().position(|cells| cells.iter().all(|c| is_separator(c)));
+    fill_table(node, &lines, separator);
+}
+
+/// Replace contents of a table block with sections of rows, given cells of each line
+fn fill_table(node: &mut Node, lines: &[Vec<String>], separator: Option<usize>) {
+    let columns = lines.iter().map(Vec::len).max().unwrap_or_default();
+
+    let props_align = node.find_prop("align");
+    let mut props_align = props_align.iter().flat_map(|a| a.split_whitespace());
+    let align = (0..columns)
+        .map(|col| {
+            let from_separator = separator.and_then(|idx| lines[idx].get(col)).map(|c| {
+                match (c.starts_with(':'), c.ends_with(':')) {
+                    (true, true) => "center",
+                    (true, false) => "left",
+                    (false, true) => "right",
+                    (false, false) => "",
+                }
+            });
+            match props_align.next() {
+                Some(a @ ("left" | "right" | "center")) => a,
+                _ => from_separator.unwrap_or_default(),
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let rows = lines
+        .iter()
+        .enumerate()
+        .filter(|(idx, _)| Some(*idx) != separator)
+        .map(|(_, cells)| cells)
+        .collect::<Vec<_>>();
+    let widths = (0..columns)
+        .map(|col| {
+            let cells = rows.iter().filter_map(|cells| cells.get(col));
+            cells.map(|c| c.chars().count()).max().unwrap_or_default().max(3)
+        })
+        .collect::<Vec<_>>();
+
+    let row = |cells: &[String]| {
+        let cells = (0..columns).map(|col| {
+            let value = cells.get(col).map(String::as_str).unwrap_or_default();
+            let padding = widths[col] - value.chars().count();
+            let (lpad, rpad) = match align[col] {
+                "right" => (padding, 0),
+                "center" => (padding / 2, padding - padding / 2),
+                _ => (0, padding),
+            };
+            let mut cell = Node::line(value);
+            if !align[col].is_empty() {
+                cell.add_prop("align", Arc::from(align[col]));
+            }
+            cell.add_prop("lpad", Arc::from(" ".repeat(lpad)));
+            cell.add_prop("rpad", Arc::from(" ".repeat(rpad)));
+            cell
+        });
+        NodeBuilder::new(Rule::Block)
+            .headers(Some(vec![Arc::from("ROW")]))
+            .add_section(cells.collect())
+            .done()
+    };
+    let section = |header: &str, rows: Vec<Node>| {
+        NodeBuilder::new(Rule::Section)
+            .headers(Some(vec![Arc::from(header)]))
+            .add_children(rows)
+            .done()
+    };
+
+    let mut sections = Vec::new();
+    if let Some(caption) = node.find_prop("caption") {
+        sections.push(section("CAPTION", vec![Node::line(&caption)]));
+    }
+    let (head, body) = rows.split_at(separator.unwrap_or_default());
+    let rule = (0..columns).map(|col| {
+        let dashes = |n| "-".repeat(n);
+        match align[col] {
+            "left" => format!(":{}", dashes(widths[col] - 1)),
+            "right" => format!("{}:", dashes(widths[col] - 1)),
+            "center" => format!(":{}:", dashes(widths[col] - 2)),
+            _ => dashes(widths[col]),
+        }
+    });
+    let rule = row(&rule.collect::<Vec<_>>());
+    match separator {
+        Some(_) => {
+            sections.push(section("HEAD", head.iter().map(|cells| row(cells)).collect()));
+            sections.push(section("RULE", vec![rule]));
+        }
+        // NOTE: formats that require a header, like markdown, render an empty one
+        None => sections.push(section("STUB", vec![row(&[]), rule])),
+    }
+    sections.push(section("BODY", body.iter().map(|cells| row(cells)).collect()));
+    node.children = Some(sections);
+}
+
+/// Split a table line to trimmed cells, where the leading pipe is the block marker
+fn split_cells(line: &str) -> Vec<String> {
+    let mut cells = vec![String::new()];
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.peek() == Some(&'|') => {
+                cells.last_mut().unwrap().push('|');
+                chars.next();
+            }
+            '|' => cells.push(String::new()),
+            c => cells.last_mut().unwrap().push(c),
+        }
+    }
+    if line.trim_end().ends_with('|') && cells.last().is_some_and(|c| c.trim().is_empty()) {
+        cells.pop();
+    }
+    cells.into_iter().map(|c| c.trim().to_string()).collect()
+}
+
+fn is_separator(cell: &str) -> bool {
+    let dashes = cell.trim_start_matches(':').trim_end_matches(':');
+    !dashes.is_empty() && dashes.chars().all(|c| c == '-')
+}
+
 /// Moves nodes with id to asts
 fn preprocess_ids(
     node: &mut Node,
@@ -202,15 +965,18 @@ fn preprocess_includes(
     context: &str,
     deps: &mut HashSet<Dependency>,
     settings: &LangSettings,
+    lang: &Lang,
 ) {
     let props = node
         .props
         .as_ref()
         .map_or(&[] as &[_], Vec::as_slice)
         .iter()
-        .filter(|&(k, _)| PREPROCESSABLE_PROPS.contains(&&**k));
+        .filter(|&(k, _)| PREPROCESSABLE_PROPS.contains(&&**k))
+        .cloned()
+        .collect::<Vec<_>>();
 
-    for (key, uri_or_path) in props {
+    for (key, uri_or_path) in &props {
         let (scheme, path) = match &**key {
             "src" => uri_or_path
                 .split_once(':')
@@ -226,6 +992,55 @@ fn preprocess_includes(
             None => (scheme, false),
         };
 
+        if scheme == "index" {
+            let sort = node.find_prop("sort");
+            let order = node.find_prop("order");
+            let items = index_links(path, sort, order, asts, locs, context, deps, lang);
+
+            let headers = node.headers.get_or_insert_with(Default::default);
+            let header = Arc::from("LIST");
+            if !headers.contains(&header) {
+                headers.push(header);
+            }
+            let section = NodeBuilder::new(Rule::Section).add_children(items).done();
+            node.children = Some(vec![section]);
+            continue;
+        }
+
+        let kind = match &**key {
+            "ref" => "ref",
+            "src" => "src",
+            _ => unreachable!(),
+        };
+
+        // NOTE: glob and directory paths expand to one include per match
+        if !is_resolved && is_glob(path) {
+            let matches = resolve_glob(path, locs.keys().map(String::as_str), context);
+            let ellipses = matches
+                .into_iter()
+                .map(|path| {
+                    let arc = insert_include(kind, format!("{scheme}:{path}"), asts, deps);
+                    Node::ellipsis(Some(Pointer(Arc::downgrade(arc))))
+                })
+                .collect::<Vec<_>>();
+
+            if kind == "ref" {
+                continue;
+            }
+
+            // NOTE: the aggregate node is owned by asts, but never scheduled
+            let aggregate = NodeBuilder::root().add_section(ellipses).done();
+            let arc = match asts.entry(format!("{scheme}:{context}?{path}")) {
+                Entry::Occupied(r) => {
+                    *r.get().lock().expect("poisoned lock") = aggregate;
+                    &r.get().clone()
+                }
+                Entry::Vacant(r) => &*r.insert(Arc::new(Mutex::new(aggregate))),
+            };
+            add_pointer(node, Pointer(Arc::downgrade(arc)));
+            continue;
+        }
+
         let uri_path = if is_resolved {
             // NOTE: schemes with ? are pre-resolved
             path.to_string()
@@ -233,6 +1048,20 @@ fn preprocess_includes(
             // TODO: improve and clarify resolving
             let (path, fragment) = path.rsplit_once('#').unwrap_or((path, ""));
 
+            // NOTE: line ranges and regions are selected once the whole file is loaded
+            let fragment = match source::selector(fragment) {
+                Some((key, value)) if kind == "src" => {
+                    node.set_prop(key, Arc::from(value));
+                    ""
+                }
+                _ => fragment,
+            };
+            if let (true, None, Some(language)) =
+                (kind == "src", node.find_prop("language"), source::language(path))
+            {
+                node.add_prop("language", Arc::from(language));
+            }
+
             // NOTE: first resolve URI path to canonical form
             let prefix =
                 resolve_path(path, locs.keys().map(String::as_str), context).unwrap_or_default();
@@ -252,42 +1081,188 @@ fn preprocess_includes(
         };
 
         let uri = format!("{scheme}:{uri_path}");
+        let arc = insert_include(kind, uri, asts, deps);
 
-        // add dependency
-        match &**key {
-            "ref" => deps.insert(Dependency::URI("ref", uri.clone())),
-            "src" => deps.insert(Dependency::URI("src", uri.clone())),
-            _ => unreachable!(),
-        };
-
-        // add placeholder node to ast
-        let arc = asts.entry(uri.clone()).or_insert_with(|| {
-            let root = NodeBuilder::root().build().unwrap();
-            Arc::new(Mutex::new(root))
-        });
-
-        if &**key == "ref" {
+        if kind == "ref" {
             continue;
         }
 
-        // add pointer to node
-        let pointer = Some(Pointer(Arc::downgrade(arc)));
-        if let Some(children) = node.children.as_mut() {
-            if let Some(node) = get_ellipsis_node_recursive(children.as_mut_slice()) {
-                node.pointer = pointer;
-            } else {
-                node.pointer = pointer;
-            }
+        add_pointer(node, Pointer(Arc::downgrade(arc)));
+    }
+}
+
+/// Adds dependency and placeholder node for an include
+fn insert_include<'a>(
+    kind: &'static str,
+    uri: String,
+    asts: &'a mut AstMap,
+    deps: &mut HashSet<Dependency>,
+) -> &'a Arc<Mutex<Node>> {
+    deps.insert(Dependency::URI(kind, uri.clone()));
+    asts.entry(uri).or_insert_with(|| {
+        let root = NodeBuilder::root().build().unwrap();
+        Arc::new(Mutex::new(root))
+    })
+}
+
+/// Adds include pointer to ellipsis node, node or new section
+fn add_pointer(node: &mut Node, pointer: Pointer) {
+    let pointer = Some(pointer);
+    if let Some(children) = node.children.as_mut() {
+        if let Some(node) = get_ellipsis_node_recursive(children.as_mut_slice()) {
+            node.pointer = pointer;
         } else {
-            let section = NodeBuilder::new(Rule::Section)
-                .pointer(pointer)
-                .build()
-                .unwrap();
-            node.children = Some(vec![section]);
+            node.pointer = pointer;
         }
+    } else {
+        let section = NodeBuilder::new(Rule::Section)
+            .pointer(pointer)
+            .build()
+            .unwrap();
+        node.children = Some(vec![section]);
     }
 }
 
+/// Build links to indexed documents under a path
+#[allow(clippy::too_many_arguments)]
+fn index_links(
+    path: &str,
+    sort: Option<Arc<str>>,
+    order: Option<Arc<str>>,
+    asts: &AstMap,
+    locs: &LocationMap,
+    context: &str,
+    deps: &mut HashSet<Dependency>,
+    lang: &Lang,
+) -> Vec<Node> {
+    let context_dir = context.rsplit_once('/').map_or("", |(dir, _)| dir);
+    let mut entries = index_paths(path, locs, context)
+        .into_iter()
+        .map(|key| {
+            let props = match asts.get(&format!("ast:{key}")) {
+                Some(arc) => arc.lock().expect("poisoned lock").props.clone(),
+                None => None,
+            };
+            (key, props.unwrap_or_default())
+        })
+        .collect::<Vec<_>>();
+
+    let find = |props: &Props, key: &str| {
+        props
+            .iter()
+            .find_map(|(k, v)| if &**k == key { Some(v.clone()) } else { None })
+    };
+    entries.sort_by_key(|(key, props)| match sort.as_deref() {
+        Some(prop) => (find(props, prop).unwrap_or_default(), key.to_string()),
+        None => (Arc::from(""), key.to_string()),
+    });
+    if order.as_deref() == Some("desc") {
+        entries.reverse();
+    }
+
+    let ext = mime2ext(&lang.media_type);
+    entries
+        .into_iter()
+        .map(|(key, props)| {
+            deps.insert(Dependency::URI("ref", format!("write:{key}")));
+
+            let target = match ext {
+                Some(ext) => Path::new(key).with_extension(ext),
+                None => PathBuf::from(key),
+            };
+            let href = relative_path(context_dir, &target.display().to_string());
+            let title = find(&props, "title").unwrap_or_else(|| {
+                let stem = Path::new(key).file_stem().unwrap_or_default();
+                Arc::from(stem.to_string_lossy().as_ref())
+            });
+
+            let mut link = NodeBuilder::block(">")
+                .headers(Some(vec![Arc::from("LINK")]))
+                .add_prop((Arc::from("href"), Arc::from(href)))
+                .add_section(vec![Node::line(&title)])
+                .done();
+            for (k, v) in props {
+                if !INDEX_IGNORED_PROPS.contains(&&*k) {
+                    link.add_prop(&k, v);
+                }
+            }
+            link
+        })
+        .collect()
+}
+
+/// Find paths of documents indexed under a path
+fn index_paths<'a>(path: &str, locs: &'a LocationMap, context: &str) -> Vec<&'a String> {
+    let path = path.trim_start_matches("./");
+    let context_dir = context.rsplit_once('/').map_or("", |(dir, _)| dir);
+    let is_indexable = |k: &&String| k.ends_with(INDEXABLE_EXTENSION) && k.as_str() != context;
+
+    // NOTE: first search relative to context, then from root
+    let prefix = match context_dir.is_empty() {
+        true => path.to_string(),
+        false => format!("{context_dir}/{path}"),
+    };
+    // NOTE: whole components are matched, so that `posts` does not index `posts-old`
+    let is_under = |k: &&String, dir: &str| Path::new(k.as_str()).starts_with(dir);
+    let paths = locs
+        .keys()
+        .filter(is_indexable)
+        .filter(|k| is_under(k, &prefix))
+        .collect::<Vec<_>>();
+    match paths.is_empty() {
+        true => locs.keys().filter(is_indexable).filter(|k| is_under(k, path)).collect(),
+        false => paths,
+    }
+}
+
+/// Find documents indexed by an AST, so that they can be parsed before it is preprocessed
+///
+/// Index blocks are expanded with props of the indexed documents, which are only available once
+/// they have been parsed.
+pub fn index_targets(node: &Node, locs: &LocationMap, context: &str, lang: &Lang) -> Vec<String> {
+    let mut targets = Vec::new();
+    index_targets_recursive(node, locs, context, lang, "", &mut targets);
+    targets.sort();
+    targets.dedup();
+    targets
+}
+
+fn index_targets_recursive(
+    node: &Node,
+    locs: &LocationMap,
+    context: &str,
+    lang: &Lang,
+    base_path: &str,
+    targets: &mut Vec<String>,
+) {
+    let path = node.build_path(base_path);
+    let mut rules = lang.get_rules("PREPROCESS", &path, node).peekable();
+    let mut settings = rules.peek().map(|r| r.settings).unwrap_or_default();
+    rules.for_each(|rule| settings.merge(&rule.settings));
+
+    for (_, value) in node.props.iter().flatten().filter(|(k, _)| &**k == "src") {
+        let index_path = match value.split_once(':') {
+            Some(("index", path)) => path,
+            None if settings.default_src == Some("index") => value,
+            _ => continue,
+        };
+        targets.extend(index_paths(index_path, locs, context).into_iter().cloned());
+    }
+
+    for child in node.children.iter().flatten() {
+        index_targets_recursive(child, locs, context, lang, &path, targets);
+    }
+}
+
+/// Build path relative to a directory
+fn relative_path(from_dir: &str, to: &str) -> String {
+    let from = from_dir.split('/').filter(|s| !s.is_empty()).collect::<Vec<_>>();
+    let to = to.split('/').collect::<Vec<_>>();
+    let common = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+    let up = "../".repeat(from.len() - common);
+    format!("{up}{}", to[common..].join("/"))
+}
+
 /// Join adjacent lines in sections into paragraphs
 fn preprocess_paragraphs(node: &mut Node, settings: &LangSettings) {
     if !settings.is_paragraphable {
@@ -414,6 +1389,64 @@ where
     None
 }
 
+/// Check if path is a glob or directory path
+pub fn is_glob(path: &str) -> bool {
+    path.contains('*') || path.ends_with('/')
+}
+
+/// Resolve glob or directory path to all matching entries from a list, sorted
+pub fn resolve_glob<'a, I>(path: &str, paths: I, context: &str) -> Vec<&'a str>
+where
+    I: Iterator<Item = &'a str>,
+{
+    let path = path.trim_start_matches("./");
+    let pattern = match path.ends_with('/') {
+        true => format!("{path}**"),
+        false => path.to_string(),
+    };
+    let context_dir = context.rsplit_once('/').map_or("", |(dir, _)| dir);
+    let paths = paths.filter(|p| *p != context).collect::<Vec<_>>();
+
+    // NOTE: first search relative to context, then from root
+    let mut result = match context_dir.is_empty() {
+        true => Vec::new(),
+        false => {
+            let regex = glob_regex(&format!("{context_dir}/{pattern}"));
+            paths.iter().copied().filter(|p| regex.is_match(p)).collect()
+        }
+    };
+    if result.is_empty() {
+        let regex = glob_regex(&pattern);
+        result = paths.into_iter().filter(|p| regex.is_match(p)).collect();
+    }
+    result.sort();
+    result
+}
+
+/// Convert glob pattern to an anchored regex
+fn glob_regex(pattern: &str) -> Regex {
+    let mut result = String::from("^");
+    let mut chars = pattern.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                match chars.peek() {
+                    Some('/') => {
+                        chars.next();
+                        result.push_str("(.*/)?");
+                    }
+                    _ => result.push_str(".*"),
+                }
+            }
+            '*' => result.push_str("[^/]*"),
+            c => result.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+        }
+    }
+    result.push('$');
+    Regex::new(&result).expect("escaped glob pattern")
+}
+
 /// Resolve path to matching entry from a list with a given scheme prefix
 pub fn resolve_scheme_path<'a, I>(
     path: &str,
@@ -451,7 +1484,7 @@ mod tests {
         let mut locs = LocationMap::default();
         locs.insert("bar".to_string(), PathBuf::from("something.txt").into());
         let lang = Lang::markdown();
-        preprocess(&mut node, None, &mut asts, &mut locs, "", &lang).unwrap();
+        preprocess(&mut node, None, &mut asts, &locs, "", &lang).unwrap();
 
         let section = node.children.as_ref().unwrap().first().unwrap();
         let block = section.children.as_ref().unwrap().first().unwrap();
@@ -469,7 +1502,7 @@ mod tests {
         let mut locs = LocationMap::default();
         locs.insert("bar".to_string(), PathBuf::from("something.txt").into());
         let lang = Lang::markdown();
-        preprocess(&mut node, None, &mut asts, &mut locs, "", &lang).unwrap();
+        preprocess(&mut node, None, &mut asts, &locs, "", &lang).unwrap();
 
         let section = node.children.as_ref().unwrap().first().unwrap();
         let block = section.children.as_ref().unwrap().first().unwrap();
@@ -492,10 +1525,10 @@ mod tests {
                 ])
                 .done()])
             .done();
-        let mut locs = LocationMap::default();
+        let locs = LocationMap::default();
         let lang = Lang::markdown();
 
-        preprocess(&mut node, None, &mut asts, &mut locs, "", &lang).unwrap();
+        preprocess(&mut node, None, &mut asts, &locs, "", &lang).unwrap();
 
         let block = node.children.as_ref().unwrap().first().unwrap();
         let section = block.children.as_ref().unwrap().first().unwrap();
@@ -503,8 +1536,7 @@ mod tests {
             .children
             .as_ref()
             .unwrap()
-            .into_iter()
-            .nth(1)
+            .get(1)
             .unwrap();
 
         assert_eq!(ellipsis.rule, Rule::Ellipsis);
@@ -519,9 +1551,9 @@ mod tests {
                 .add_prop(("ref".into(), "bar".into()))
                 .done()])
             .done();
-        let mut locs = LocationMap::default();
+        let locs = LocationMap::default();
         let lang = Lang::markdown();
-        preprocess(&mut node, None, &mut asts, &mut locs, "", &lang).unwrap();
+        preprocess(&mut node, None, &mut asts, &locs, "", &lang).unwrap();
 
         let section = node.children.as_ref().unwrap().first().unwrap();
         let block = section.children.as_ref().unwrap().first().unwrap();
@@ -558,7 +1590,7 @@ mod tests {
         );
         let lang = Lang::markdown();
 
-        preprocess(&mut node, None, &mut asts, &mut locs, "file.md", &lang).unwrap();
+        preprocess(&mut node, None, &mut asts, &locs, "file.md", &lang).unwrap();
 
         let mut ast_keys = asts.keys().collect::<Vec<_>>();
         ast_keys.sort();
@@ -591,9 +1623,9 @@ mod tests {
             .done();
 
         let mut node = NodeBuilder::root().children(vec![block.clone()]).done();
-        let mut locs = LocationMap::default();
+        let locs = LocationMap::default();
         let lang = Lang::markdown();
-        preprocess(&mut node, None, &mut asts, &mut locs, "foo", &lang).unwrap();
+        preprocess(&mut node, None, &mut asts, &locs, "foo", &lang).unwrap();
 
         let moved_block = asts.get("parse:foo#bar").unwrap().lock().unwrap();
         assert_eq!(*moved_block, expected_block);
@@ -607,9 +1639,9 @@ mod tests {
             .done();
 
         let mut node = NodeBuilder::root().children(vec![block.clone()]).done();
-        let mut locs = LocationMap::default();
+        let locs = LocationMap::default();
         let lang = Lang::markdown();
-        preprocess(&mut node, None, &mut asts, &mut locs, "foo", &lang).unwrap();
+        preprocess(&mut node, None, &mut asts, &locs, "foo", &lang).unwrap();
 
         let new_block = node.children.as_ref().unwrap().first().unwrap();
         assert!(new_block.pointer.is_some());
@@ -635,7 +1667,7 @@ mod tests {
         let lang = Lang::markdown();
         locs.insert("file.md".to_string(), PathBuf::from("file.md").into());
         locs.insert("other.md".to_string(), PathBuf::from("other.md").into());
-        preprocess(&mut node, None, &mut asts, &mut locs, "file.md", &lang).unwrap();
+        preprocess(&mut node, None, &mut asts, &locs, "file.md", &lang).unwrap();
 
         let section = node.children.as_ref().unwrap().first().unwrap();
         let block = section.children.as_ref().unwrap().first().unwrap();
@@ -660,7 +1692,7 @@ mod tests {
         let lang = Lang::markdown();
         locs.insert("file.md".to_string(), PathBuf::from("file.md").into());
         let (deps, new_asts) =
-            preprocess(&mut node, None, &mut asts, &mut locs, "file.md", &lang).unwrap();
+            preprocess(&mut node, None, &mut asts, &locs, "file.md", &lang).unwrap();
 
         assert_eq!(
             deps,
@@ -669,6 +1701,48 @@ mod tests {
         assert_eq!(new_asts, HashSet::from(["parse:file.md#code".to_string()]));
     }
 
+    #[test]
+    fn test_preprocess_expands_glob_to_pointers() {
+        let mut asts = AstMap::default();
+        let mut node = NodeBuilder::root()
+            .add_section(vec![NodeBuilder::block(">")
+                .add_prop(("src".into(), "chapters/*.md".into()))
+                .done()])
+            .done();
+        let mut locs = LocationMap::default();
+        for path in ["book.md", "chapters/b.md", "chapters/a.md", "chapters/a.txt"] {
+            locs.insert(path.to_string(), PathBuf::from(path).into());
+        }
+        let lang = Lang::markdown();
+        let (deps, _) = preprocess(&mut node, None, &mut asts, &locs, "book.md", &lang).unwrap();
+
+        assert_eq!(
+            deps,
+            HashSet::from([
+                Dependency::URI("src", "parse:chapters/a.md".to_string()),
+                Dependency::URI("src", "parse:chapters/b.md".to_string()),
+            ])
+        );
+
+        let section = node.children.as_ref().unwrap().first().unwrap();
+        let block = section.children.as_ref().unwrap().first().unwrap();
+        let section = block.children.as_ref().unwrap().first().unwrap();
+        let Some(Pointer(weak)) = section.pointer.as_ref() else {
+            panic!("expected pointer");
+        };
+        let aggregate = weak.upgrade().unwrap();
+        let aggregate = aggregate.lock().unwrap();
+        let section = aggregate.children.as_ref().unwrap().first().unwrap();
+        let ellipses = section.children.as_ref().unwrap();
+        assert_eq!(ellipses.len(), 2);
+        for (ellipsis, uri) in ellipses.iter().zip(["parse:chapters/a.md", "parse:chapters/b.md"]) {
+            let Some(Pointer(weak)) = ellipsis.pointer.as_ref() else {
+                panic!("expected pointer");
+            };
+            assert!(Arc::ptr_eq(&weak.upgrade().unwrap(), &asts[uri]));
+        }
+    }
+
     #[test]
     fn test_preprocess_runs_precompile() {
         let mut asts = AstMap::default();
@@ -682,7 +1756,7 @@ mod tests {
         let lang = Lang::markdown();
 
         let (deps, _) =
-            preprocess(&mut node, None, &mut asts, &mut locs, "file.md", &lang).unwrap();
+            preprocess(&mut node, None, &mut asts, &locs, "file.md", &lang).unwrap();
 
         assert_eq!(
             deps,
@@ -715,9 +1789,9 @@ mod tests {
         };
         let mut asts = AstMap::default();
         let mut node = NodeBuilder::root().add_section(vec![Node::line("")]).done();
-        let mut locs = LocationMap::default();
+        let locs = LocationMap::default();
         let lang = Lang::new(rules).unwrap();
-        preprocess(&mut node, None, &mut asts, &mut locs, "", &lang).unwrap();
+        preprocess(&mut node, None, &mut asts, &locs, "", &lang).unwrap();
 
         let section = node.children.as_ref().unwrap().first().unwrap();
         let children = section.children.as_ref().unwrap();
@@ -751,9 +1825,9 @@ mod tests {
                 Node::line("bar"),
             ])
             .done();
-        let mut locs = LocationMap::default();
+        let locs = LocationMap::default();
         let lang = Lang::new(rules).unwrap();
-        preprocess(&mut node, None, &mut asts, &mut locs, "", &lang).unwrap();
+        preprocess(&mut node, None, &mut asts, &locs, "", &lang).unwrap();
 
         let section = node.children.as_ref().unwrap().first().unwrap();
         let children = section.children.as_ref().unwrap();
@@ -773,17 +1847,396 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_index_paths_match_whole_components() {
+        let mut locs = LocationMap::default();
+        for path in ["blog.md", "posts/a.md", "posts-old/b.md", "posts/c.txt"] {
+            locs.insert(path.to_string(), PathBuf::from(path).into());
+        }
+
+        let mut paths = index_paths("posts", &locs, "blog.md");
+        paths.sort();
+        assert_eq!(paths, ["posts/a.md"]);
+        assert_eq!(index_paths("./posts/", &locs, "blog.md"), ["posts/a.md"]);
+    }
+
+    #[test]
+    fn test_preprocess_expands_index_to_links() {
+        let mut asts = AstMap::default();
+        for (path, title, date) in [
+            ("blog/posts/a.md", "First", "2024-01-01"),
+            ("blog/posts/b.md", "Second", "2024-02-01"),
+        ] {
+            let root = NodeBuilder::root()
+                .add_prop(("title".into(), title.into()))
+                .add_prop(("date".into(), date.into()))
+                .done();
+            asts.insert(format!("ast:{path}"), Arc::new(Mutex::new(root)));
+        }
+        let mut node = NodeBuilder::root()
+            .add_section(vec![NodeBuilder::block(">")
+                .headers(Some(vec![Arc::from("INDEX")]))
+                .add_prop(("src".into(), "posts/".into()))
+                .add_prop(("sort".into(), "date".into()))
+                .add_prop(("order".into(), "desc".into()))
+                .done()])
+            .done();
+        let mut locs = LocationMap::default();
+        for path in ["blog/blog.md", "blog/posts/a.md", "blog/posts/b.md", "blog/posts/c.md"] {
+            locs.insert(path.to_string(), PathBuf::from(path).into());
+        }
+        let lang = Lang::markdown();
+
+        let (deps, _) =
+            preprocess(&mut node, None, &mut asts, &locs, "blog/blog.md", &lang).unwrap();
+
+        let section = node.children.as_ref().unwrap().first().unwrap();
+        let block = section.children.as_ref().unwrap().first().unwrap();
+        assert_eq!(block.headers, Some(vec!["INDEX".into(), "LIST".into()]));
+
+        let links = block.children.as_ref().unwrap()[0].children.as_ref().unwrap();
+        let result = links
+            .iter()
+            .map(|n| (n.find_prop("href").unwrap(), n.children.as_ref().unwrap()[0].clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            result,
+            vec![
+                (
+                    "posts/b.md".into(),
+                    NodeBuilder::new(Rule::Section).add_children([Node::line("Second")]).done()
+                ),
+                (
+                    "posts/a.md".into(),
+                    NodeBuilder::new(Rule::Section).add_children([Node::line("First")]).done()
+                ),
+                (
+                    "posts/c.md".into(),
+                    NodeBuilder::new(Rule::Section).add_children([Node::line("c")]).done()
+                ),
+            ]
+        );
+        assert!(deps.contains(&Dependency::URI("ref", "write:blog/posts/a.md".to_string())));
+        assert_eq!(deps.len(), 3);
+    }
+
+    #[test]
+    fn test_preprocess_headings_adds_anchors_and_fills_toc() {
+        let heading = |children| {
+            NodeBuilder::block("#")
+                .headers(Some(vec![Arc::from("HEADING")]))
+                .add_section(children)
+                .done()
+        };
+        let included = Arc::new(Mutex::new(
+            NodeBuilder::root()
+                .add_section(vec![heading(vec![heading(vec![Node::line("Intro")])])])
+                .done(),
+        ));
+        let mut node = NodeBuilder::root()
+            .add_section(vec![
+                heading(vec![Node::line("Intro")]),
+                NodeBuilder::block(">")
+                    .headers(Some(vec![Arc::from("TOC")]))
+                    .done(),
+                NodeBuilder::block(">")
+                    .add_section(vec![Node::ellipsis(Some(Pointer(Arc::downgrade(&included))))])
+                    .done(),
+            ])
+            .done();
+
+        preprocess_headings(&mut node);
+
+        let section = &node.children.as_ref().unwrap()[0];
+        let children = section.children.as_ref().unwrap();
+        assert_eq!(children[0].find_prop("anchor"), Some("intro".into()));
+
+        let included = included.lock().unwrap();
+        let outer = &included.children.as_ref().unwrap()[0].children.as_ref().unwrap()[0];
+        let inner = &outer.children.as_ref().unwrap()[0].children.as_ref().unwrap()[0];
+        assert_eq!(outer.find_prop("anchor"), Some("intro-1".into()));
+        assert_eq!(inner.find_prop("anchor"), Some("intro-1".into()));
+
+        let link = |href: &str| {
+            NodeBuilder::block(">")
+                .headers(Some(vec![Arc::from("LINK")]))
+                .add_prop((Arc::from("href"), Arc::from(href)))
+                .add_section(vec![Node::line("Intro")])
+                .done()
+        };
+        let list = |items| {
+            NodeBuilder::block("*")
+                .headers(Some(vec![Arc::from("LIST")]))
+                .add_section(items)
+                .done()
+        };
+        let toc = &children[1].children.as_ref().unwrap()[0].children.as_ref().unwrap()[0];
+        assert_eq!(*toc, list(vec![link("#intro"), list(vec![link("#intro-1")])]));
+    }
+
+    #[test]
+    fn test_preprocess_xrefs_numbers_targets_and_fills_links() {
+        let block = |headers: &str, props: &[(&str, &str)]| {
+            let props = props.iter().map(|(k, v)| (Arc::from(*k), Arc::from(*v)));
+            NodeBuilder::block(">")
+                .headers(Some(vec![Arc::from(headers)]))
+                .props(props.collect::<Props>())
+                .done()
+        };
+        // NOTE: nodes moved to asts are reached both as pointer and pointee
+        let stored = Arc::new(Mutex::new(block("CODE", &[("id", "b")])));
+        let mut pointer = block("CODE", &[("id", "b")]);
+        pointer.pointer = Some(Pointer(Arc::downgrade(&stored)));
+        let mut node = NodeBuilder::root()
+            .add_section(vec![
+                block("XREF", &[("to", "#b")]),
+                block("CODE", &[("id", "a")]),
+                pointer,
+                block("FIGURE", &[("id", "c")]),
+                block("LINK", &[("href", "#c")]),
+                block("XREF", &[("to", "missing")]),
+                block("CODE", &[("caption", "Greeting")]),
+            ])
+            .done();
+
+        let warnings = preprocess_xrefs(&mut node);
+        assert_eq!(warnings, vec![LibWarning::UnknownXref("missing".to_string())]);
+
+        let children = node.children.as_ref().unwrap()[0].children.as_ref().unwrap();
+        assert_eq!(children[1].find_prop("number"), None);
+        assert_eq!(children[2].find_prop("number"), Some("1".into()));
+        assert_eq!(stored.lock().unwrap().find_prop("number"), Some("1".into()));
+        assert_eq!(children[3].find_prop("number"), Some("1".into()));
+        assert_eq!(children[3].headers, Some(vec!["FIGURE".into()]));
+        assert_eq!(children[6].find_prop("number"), Some("2".into()));
+        assert_eq!(children[6].find_prop("label"), Some("Listing 2: Greeting".into()));
+
+        assert_eq!(block_text(&children[0]), "Listing 1");
+        assert_eq!(children[0].headers, Some(vec!["XREF".into(), "LINK".into()]));
+        assert_eq!(children[0].find_prop("href"), Some("#b".into()));
+        assert_eq!(block_text(&children[4]), "Figure 1");
+        assert_eq!(block_text(&children[5]), "missing");
+    }
+
+    #[test]
+    fn test_preprocess_splits_table_to_rows_and_cells() {
+        let mut asts = AstMap::default();
+        let mut node = NodeBuilder::root()
+            .add_section(vec![NodeBuilder::block("|")
+                .add_prop(("caption".into(), "Sizes".into()))
+                .add_prop(("align".into(), "- center".into()))
+                .add_section(vec![
+                    Node::line("Name | Size |"),
+                    Node::line("--- | ---: |"),
+                    Node::line("Ant | 1 |"),
+                    Node::line("Elephant"),
+                ])
+                .done()])
+            .done();
+        let locs = LocationMap::default();
+        let lang = Lang::markdown();
+
+        preprocess(&mut node, None, &mut asts, &locs, "", &lang).unwrap();
+
+        let section = &node.children.as_ref().unwrap()[0];
+        let table = &section.children.as_ref().unwrap()[0];
+        assert_eq!(table.headers, Some(vec!["TABLE".into()]));
+
+        let sections = table.children.as_ref().unwrap();
+        let headers = sections.iter().map(|s| s.headers.clone().unwrap()).collect::<Vec<_>>();
+        let expected = ["CAPTION", "HEAD", "RULE", "BODY"].map(|h| vec![Arc::from(h)]);
+        assert_eq!(headers, expected);
+
+        let rows = |section: &Node| {
+            let rows = section.children.iter().flatten();
+            rows.map(|row| {
+                let cells = row.children.as_ref().unwrap()[0].children.iter().flatten();
+                cells
+                    .map(|cell| {
+                        let prop = |key| cell.find_prop(key).unwrap_or_default();
+                        let value = cell.value.as_deref().unwrap_or_default();
+                        format!("{}{value}{}|{}", prop("lpad"), prop("rpad"), prop("align"))
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>()
+        };
+        assert_eq!(sections[0].children, Some(vec![Node::line("Sizes")]));
+        assert_eq!(rows(&sections[1]), [["Name    |", "Size|center"]]);
+        assert_eq!(rows(&sections[2]), [["--------|", ":--:|center"]]);
+        assert_eq!(
+            rows(&sections[3]),
+            [["Ant     |", " 1  |center"], ["Elephant|", "    |center"]]
+        );
+    }
+
+    #[test]
+    fn test_split_cells() {
+        assert_eq!(split_cells("a | b |"), ["a", "b"]);
+        assert_eq!(split_cells(" a | b"), ["a", "b"]);
+        assert_eq!(split_cells("| b |"), ["", "b"]);
+        assert_eq!(split_cells(r"a \| b | c"), ["a | b", "c"]);
+        assert!(is_separator(":--"));
+        assert!(!is_separator("::"));
+        assert!(!is_separator("a-"));
+    }
+
+    #[test]
+    fn test_preprocess_selects_lines_of_source_includes() {
+        let mut asts = AstMap::default();
+        let mut node = NodeBuilder::root()
+            .add_section(vec![
+                NodeBuilder::block(">")
+                    .headers(Some(vec!["CODE".into()]))
+                    .add_prop(("src".into(), "lib.rs#L2-L3".into()))
+                    .done(),
+                NodeBuilder::block(">")
+                    .headers(Some(vec!["CODE".into()]))
+                    .add_prop(("src".into(), "lib.rs#region=body".into()))
+                    .add_prop(("dedent".into(), "true".into()))
+                    .done(),
+            ])
+            .done();
+        let mut locs = LocationMap::default();
+        locs.insert("lib.rs".to_string(), PathBuf::from("lib.rs").into());
+        let lang = Lang::markdown();
+
+        let (deps, _) = preprocess(&mut node, None, &mut asts, &locs, "file.md", &lang).unwrap();
+        assert_eq!(deps, HashSet::from([Dependency::URI("src", "parse:lib.rs".to_string())]));
+
+        let input = "fn main() {\n    // #region body\n    run();\n    // #endregion\n}\n";
+        *asts["parse:lib.rs"].lock().unwrap() = crate::source::parse(input);
+        preprocess_sources(&mut node);
+
+        let section = &node.children.as_ref().unwrap()[0];
+        let [range, region] = &section.children.as_deref().unwrap() else {
+            panic!("expected two blocks");
+        };
+        assert_eq!(range.find_prop("lines"), Some("2-3".into()));
+        assert_eq!(range.find_prop("language"), Some("rust".into()));
+        assert_eq!(region.find_prop("region"), Some("body".into()));
+
+        let lines = |block: &Node| block.children.as_ref().unwrap()[0].children.clone();
+        let expected = ["    // #region body", "    run();"].map(Node::line).to_vec();
+        assert_eq!(lines(range), Some(expected));
+        assert_eq!(lines(region), Some(vec![Node::line("run();")]));
+    }
+
+    #[test]
+    fn test_preprocess_data_fills_tables_and_lists() {
+        let csv = crate::data::parse("Name,Size\nAnt,1\nElephant,5000\n", "sizes.csv").unwrap();
+        let arc = Arc::new(Mutex::new(csv));
+        let include = |marker, props: &[(&str, &str)]| {
+            let props = props.iter().map(|&(k, v)| (Arc::from(k), Arc::from(v)));
+            let mut block = NodeBuilder::block(marker).props(props.collect::<Props>()).done();
+            preprocess_headers(&mut block, None);
+            block.pointer = Some(Pointer(Arc::downgrade(&arc)));
+            block
+        };
+        let mut node = NodeBuilder::root()
+            .add_section(vec![
+                include("|", &[("columns", "Size, Name")]),
+                include("*", &[("header", "0"), ("columns", "1")]),
+            ])
+            .done();
+
+        preprocess_data(&mut node);
+
+        let section = &node.children.as_ref().unwrap()[0];
+        let [table, list] = &section.children.as_deref().unwrap() else {
+            panic!("expected a table and a list");
+        };
+        assert!(table.pointer.is_none());
+        let rows = |section: &Node| {
+            let rows = section.children.iter().flatten();
+            rows.map(|row| {
+                let cells = row.children.as_ref().unwrap()[0].children.iter().flatten();
+                cells.map(|cell| cell.value.clone().unwrap()).collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>()
+        };
+        let sections = table.children.as_ref().unwrap();
+        assert_eq!(rows(&sections[0]), [["Size".into(), "Name".into()]]);
+        assert_eq!(
+            rows(&sections[2]),
+            [["1".into(), "Ant".into()], ["5000".into(), "Elephant".into()]]
+        );
+
+        let items = list.children.as_ref().unwrap()[0].children.clone();
+        let expected = ["Name", "Ant", "Elephant"].map(Node::line).to_vec();
+        assert_eq!(items, Some(expected));
+    }
+
+    #[test]
+    fn test_preprocess_lists_numbers_tasks_and_definitions() {
+        let mut asts = AstMap::default();
+        let mut node = NodeBuilder::root()
+            .add_section(vec![
+                NodeBuilder::block("+")
+                    .add_prop(("start".into(), "3".into()))
+                    .add_section(vec![
+                        Node::line("One"),
+                        NodeBuilder::block("+").add_section(vec![Node::line("Sub")]).done(),
+                        Node::line("Two"),
+                    ])
+                    .done(),
+                NodeBuilder::block("-")
+                    .add_section(vec![Node::line("[x] Done"), Node::line("[ ] Todo")])
+                    .done(),
+                NodeBuilder::block("-")
+                    .add_section(vec![Node::line("Term"), Node::line(": Definition")])
+                    .done(),
+            ])
+            .done();
+        let locs = LocationMap::default();
+        let lang = Lang::markdown();
+
+        preprocess(&mut node, None, &mut asts, &locs, "", &lang).unwrap();
+
+        let section = &node.children.as_ref().unwrap()[0];
+        let [ordered, tasks, definitions] = &section.children.as_deref().unwrap() else {
+            panic!("expected three lists");
+        };
+        let items = |list: &Node| list.children.as_ref().unwrap()[0].children.clone().unwrap();
+
+        assert_eq!(ordered.headers, Some(vec!["LIST".into(), "ORDERED".into()]));
+        let numbers = items(ordered).iter().map(|n| n.find_prop("number")).collect::<Vec<_>>();
+        assert_eq!(numbers, [Some("3".into()), None, Some("4".into())]);
+        let sub = &items(ordered)[1];
+        assert_eq!(items(sub)[0].find_prop("number"), Some("1".into()));
+
+        assert_eq!(tasks.headers, Some(vec!["LIST".into(), "TASKS".into()]));
+        let values = items(tasks)
+            .iter()
+            .map(|n| (n.value.clone().unwrap(), n.find_prop("mark").unwrap()))
+            .collect::<Vec<_>>();
+        assert_eq!(values, [("Done".into(), "x".into()), ("Todo".into(), " ".into())]);
+
+        assert_eq!(definitions.headers, Some(vec!["DEFINITIONS".into()]));
+        let entries = items(definitions)
+            .iter()
+            .map(|n| (n.headers.clone().unwrap(), items(n)))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            entries,
+            [
+                (vec!["TERM".into()], vec![Node::line("Term")]),
+                (vec!["DEF".into()], vec![Node::line("Definition")]),
+            ]
+        );
+    }
+
     #[test]
     fn test_preprocess_adds_extra_root_headers() {
         let mut asts = AstMap::default();
         let mut node = NodeBuilder::root()
             .headers(Some(vec![Arc::from("FOO")]))
             .done();
-        let mut locs = LocationMap::default();
+        let locs = LocationMap::default();
         let lang = Lang::markdown();
         let root = Some("BAR baz".to_string());
 
-        preprocess(&mut node, root.as_deref(), &mut asts, &mut locs, "", &lang).unwrap();
+        preprocess(&mut node, root.as_deref(), &mut asts, &locs, "", &lang).unwrap();
 
         let headers = node.headers.clone().expect("headers");
         assert_eq!(headers, vec!["BAR".into(), "BAZ".into(), "FOO".into()]);
@@ -841,6 +2294,51 @@ mod tests_resolve_path {
     }
 }
 
+#[cfg(test)]
+mod tests_resolve_glob {
+    use super::*;
+
+    const PATHS: &[&str] = &[
+        "book/book.md",
+        "book/chapters/b.md",
+        "book/chapters/a.md",
+        "book/chapters/extra/c.md",
+        "chapters/z.md",
+        "snippets/x.py",
+        "snippets/y/z.py",
+    ];
+
+    #[test]
+    fn test_glob_within_context() {
+        assert_eq!(
+            resolve_glob("chapters/*.md", PATHS.iter().copied(), "book/book.md"),
+            vec!["book/chapters/a.md", "book/chapters/b.md"]
+        );
+        assert_eq!(
+            resolve_glob("./chapters/**/*.md", PATHS.iter().copied(), "book/book.md"),
+            vec!["book/chapters/a.md", "book/chapters/b.md", "book/chapters/extra/c.md"]
+        );
+    }
+
+    #[test]
+    fn test_glob_from_root() {
+        assert_eq!(
+            resolve_glob("chapters/*.md", PATHS.iter().copied(), "other.md"),
+            vec!["chapters/z.md"]
+        );
+        assert_eq!(resolve_glob("*.md", PATHS.iter().copied(), "book/book.md"), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn test_directory() {
+        assert!(is_glob("snippets/"));
+        assert_eq!(
+            resolve_glob("snippets/", PATHS.iter().copied(), "book/book.md"),
+            vec!["snippets/x.py", "snippets/y/z.py"]
+        );
+    }
+}
+
 #[cfg(test)]
 mod tests_resolve_scheme_path {
     use std::collections::HashMap;
@@ -868,3 +2366,16 @@ mod tests_resolve_scheme_path {
         assert_eq!(resolve_scheme_path("baz#win", "this", map.keys(), ""), None);
     }
 }
+
+#[cfg(test)]
+mod tests_relative_path {
+    use super::*;
+
+    #[test]
+    fn test_relative_path() {
+        assert_eq!(relative_path("blog", "blog/posts/a.html"), "posts/a.html");
+        assert_eq!(relative_path("", "posts/a.html"), "posts/a.html");
+        assert_eq!(relative_path("blog/posts", "blog/a.html"), "../a.html");
+        assert_eq!(relative_path("blog", "other/a.html"), "../other/a.html");
+    }
+}
@@ -0,0 +1,251 @@
+use std::{
+    path::Path,
+    sync::{Arc, LazyLock},
+};
+
+use regex::Regex;
+
+use crate::{
+    ast::{Node, NodeBuilder},
+    parser::Rule,
+};
+
+static REGION_START: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"#region\b\s*(\S*)").expect("valid regex"));
+static REGION_END: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"#endregion\b").expect("valid regex"));
+static LINE_RANGE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^L(\d+)(?:-L?(\d*))?$").expect("valid regex"));
+
+static LANGUAGES: &[(&str, &str)] = &[
+    ("c", "c"),
+    ("cpp", "cpp"),
+    ("css", "css"),
+    ("go", "go"),
+    ("h", "c"),
+    ("hpp", "cpp"),
+    ("htm", "html"),
+    ("java", "java"),
+    ("js", "javascript"),
+    ("kt", "kotlin"),
+    ("mjs", "javascript"),
+    ("py", "python"),
+    ("rb", "ruby"),
+    ("rs", "rust"),
+    ("sh", "bash"),
+    ("ts", "typescript"),
+    ("yml", "yaml"),
+];
+
+/// Check if a path is a source file of a known language, which is never parsed as Murkdown
+pub fn is_source(path: &str) -> bool {
+    let ext = Path::new(path).extension().and_then(|e| e.to_str());
+    ext.is_some_and(|ext| LANGUAGES.iter().any(|(e, _)| *e == ext))
+}
+
+/// Infer language of a non-Murkdown file from its extension
+///
+/// Paths without an extension are Murkdown, since documents given as data URLs may lack one, and
+/// so are partials like `header.md.part`.
+pub fn language(path: &str) -> Option<&str> {
+    let name = Path::new(path).file_name().and_then(|n| n.to_str()).unwrap_or_default();
+    if name.split('.').skip(1).any(|ext| ext == "md") {
+        return None;
+    }
+    let ext = Path::new(path).extension().and_then(|e| e.to_str())?;
+    match LANGUAGES.iter().find(|(e, _)| *e == ext) {
+        Some((_, language)) => Some(language),
+        None => Some(ext),
+    }
+}
+
+/// Parse a source file to verbatim lines
+pub fn parse(input: &str) -> Node {
+    NodeBuilder::root().add_child(section(input)).done()
+}
+
+/// Build a `SOURCE` section of verbatim lines
+///
+/// The section has a header, so that lines are not built to paragraphs.
+pub fn section(input: &str) -> Node {
+    NodeBuilder::new(Rule::Section)
+        .headers(Some(vec![Arc::from("SOURCE")]))
+        .add_children(input.lines().map(Node::line))
+        .done()
+}
+
+/// Keep the input of a parsed Murkdown document as a `RAW` section
+///
+/// Code blocks and line or region selectors include the raw lines, while the section itself is
+/// never compiled.
+pub fn attach_raw(mut node: Node, input: &str) -> Node {
+    let mut section = section(input);
+    section.headers = Some(vec![Arc::from("RAW")]);
+    node.children.get_or_insert_with(Vec::new).push(section);
+    node
+}
+
+/// Check if a node is the `RAW` section of a Murkdown document
+pub fn is_raw(node: &Node) -> bool {
+    node.rule == Rule::Section && node.headers.as_deref() == Some(&[Arc::from("RAW")])
+}
+
+/// Get lines of a parsed source file, if the node is one
+pub fn lines(node: &Node) -> Option<Vec<Arc<str>>> {
+    section_lines(node, "SOURCE")
+}
+
+/// Get raw lines of a parsed Murkdown document, if the node is one
+pub fn raw_lines(node: &Node) -> Option<Vec<Arc<str>>> {
+    section_lines(node, "RAW")
+}
+
+fn section_lines(node: &Node, header: &str) -> Option<Vec<Arc<str>>> {
+    let mut sections = node.children.iter().flatten();
+    let section = sections.rfind(|s| s.headers.as_deref() == Some(&[Arc::from(header)]))?;
+    let lines = section.children.iter().flatten();
+    Some(lines.map(|n| n.value.clone().unwrap_or_default()).collect())
+}
+
+/// Get the include prop matching a URI fragment like `L10-L42` or `region=name`
+pub fn selector(fragment: &str) -> Option<(&'static str, String)> {
+    if let Some(name) = fragment.strip_prefix("region=") {
+        return Some(("region", name.to_string()));
+    }
+    let captures = LINE_RANGE.captures(fragment)?;
+    let range = match captures.get(2) {
+        Some(end) => format!("{}-{}", &captures[1], end.as_str()),
+        None => captures[1].to_string(),
+    };
+    Some(("lines", range))
+}
+
+/// Select lines by a 1-based inclusive range like `10-42`, `10-` or `10`
+pub fn select_lines<'a>(lines: &'a [Arc<str>], range: &str) -> &'a [Arc<str>] {
+    let (start, end) = range.split_once('-').unwrap_or((range, range));
+    let start = start.trim().parse::<usize>().unwrap_or(1).max(1) - 1;
+    let end = end.trim().parse::<usize>().unwrap_or(lines.len()).min(lines.len());
+    lines.get(start..end).unwrap_or_default()
+}
+
+/// Select lines between `#region name` and `#endregion` marker comments
+///
+/// Regions may nest, and marker comments of nested regions are left out.
+pub fn select_region(lines: &[Arc<str>], name: &str) -> Option<Vec<Arc<str>>> {
+    let is_start = |line: &str| REGION_START.captures(line).is_some_and(|c| &c[1] == name);
+    let start = lines.iter().position(|line| is_start(line))? + 1;
+
+    let mut depth = 0;
+    let mut result = Vec::new();
+    for line in &lines[start..] {
+        if REGION_START.is_match(line) {
+            depth += 1;
+        } else if REGION_END.is_match(line) {
+            if depth == 0 {
+                break;
+            }
+            depth -= 1;
+        } else {
+            result.push(line.clone());
+        }
+    }
+    Some(result)
+}
+
+/// Remove common indentation of non-blank lines, up to a width if given
+pub fn dedent(lines: &mut [Arc<str>], width: Option<usize>) {
+    let indent = |line: &str| line.len() - line.trim_start_matches([' ', '\t']).len();
+    let common = lines.iter().filter(|l| !l.trim().is_empty()).map(|l| indent(l)).min();
+    let common = common.unwrap_or_default().min(width.unwrap_or(usize::MAX));
+    for line in lines.iter_mut() {
+        let cut = common.min(indent(line));
+        *line = Arc::from(&line[cut..]);
+    }
+}
+
+/// Remove leading and trailing blank lines
+pub fn trim(lines: &mut Vec<Arc<str>>) {
+    while lines.last().is_some_and(|l| l.trim().is_empty()) {
+        lines.pop();
+    }
+    let leading = lines.iter().take_while(|l| l.trim().is_empty()).count();
+    lines.drain(..leading);
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn arcs(values: &[&str]) -> Vec<Arc<str>> {
+        values.iter().map(|&v| Arc::from(v)).collect()
+    }
+
+    #[test]
+    fn test_selector() {
+        assert_eq!(selector("L10-L42"), Some(("lines", "10-42".to_string())));
+        assert_eq!(selector("L10-"), Some(("lines", "10-".to_string())));
+        assert_eq!(selector("L7"), Some(("lines", "7".to_string())));
+        assert_eq!(selector("region=parse"), Some(("region", "parse".to_string())));
+        assert_eq!(selector("Lfoo"), None);
+        assert_eq!(selector("code"), None);
+    }
+
+    #[test]
+    fn test_select_lines() {
+        let lines = arcs(&["a", "b", "c", "d"]);
+        assert_eq!(select_lines(&lines, "2-3"), arcs(&["b", "c"]));
+        assert_eq!(select_lines(&lines, "3-"), arcs(&["c", "d"]));
+        assert_eq!(select_lines(&lines, "2"), arcs(&["b"]));
+        assert_eq!(select_lines(&lines, "3-9"), arcs(&["c", "d"]));
+        assert!(select_lines(&lines, "5-6").is_empty());
+    }
+
+    #[test]
+    fn test_select_region() {
+        let lines = arcs(&[
+            "fn main() {",
+            "    // #region outer",
+            "    a();",
+            "    // #region inner",
+            "    b();",
+            "    // #endregion",
+            "    // #endregion outer",
+            "}",
+        ]);
+        assert_eq!(select_region(&lines, "outer"), Some(arcs(&["    a();", "    b();"])));
+        assert_eq!(select_region(&lines, "inner"), Some(arcs(&["    b();"])));
+        assert_eq!(select_region(&lines, "missing"), None);
+    }
+
+    #[test]
+    fn test_dedent_and_trim() {
+        let mut lines = arcs(&["", "    a", "      b", "", "    c", ""]);
+        trim(&mut lines);
+        dedent(&mut lines, None);
+        assert_eq!(lines, arcs(&["a", "  b", "", "c"]));
+        dedent(&mut lines, Some(1));
+        assert_eq!(lines, arcs(&["a", "  b", "", "c"]));
+
+        let mut lines = arcs(&["    a", "      b"]);
+        dedent(&mut lines, Some(2));
+        assert_eq!(lines, arcs(&["  a", "    b"]));
+    }
+
+    #[test]
+    fn test_language() {
+        assert_eq!(language("src/lib.rs"), Some("rust"));
+        assert_eq!(language("a.zig"), Some("zig"));
+        assert_eq!(language("a.md"), None);
+        assert_eq!(language("Makefile"), None);
+        assert_eq!(language("notes.txt"), Some("txt"));
+        assert!(is_source("a.rs"));
+        assert!(!is_source("a.zig"));
+        assert!(!is_source("part.txt"));
+        assert!(!is_source("Makefile"));
+        assert!(!is_source("a.md"));
+        assert!(!is_source("header.md.part"));
+        assert_eq!(language("header.md.part"), None);
+    }
+}
@@ -84,6 +84,14 @@ pub enum LibError {
     MissingRoot,
     #[error("unknown rule section `{0}`")]
     UnknownRuleSection(String),
+    #[error("unknown rule setting `{0}`")]
+    UnknownRuleSetting(String),
+    #[error("invalid template `{0}`: {1}")]
+    InvalidTemplate(String, &'static str),
+    #[error("invalid data `{0}`: {1}")]
+    InvalidData(String, String),
+    #[error("could not load `{0}`")]
+    UnresolvedLoad(String),
     #[error("invalid rule `{0}`")]
     InvalidRule(String),
     #[error("invalid argument `{0}`")]
@@ -92,6 +100,19 @@ pub enum LibError {
     InvalidRuleArgumentType(String, &'static str),
 }
 
+/// Problem that does not stop preprocessing or compiling, left to the caller to report
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum LibWarning {
+    #[error("Variable `{0}` is not set, use `${{{0}:d()}}` to default it")]
+    UnsetVariable(String),
+    #[error("Cross-reference to unknown id `{0}`")]
+    UnknownXref(String),
+    #[error("Unknown region `{0}`")]
+    UnknownRegion(String),
+    #[error("Unknown column `{0}`")]
+    UnknownColumn(String),
+}
+
 pub trait LibErrorPathCtx<T> {
     fn with_path(self, id: &str) -> Result<T, LibError>;
 }
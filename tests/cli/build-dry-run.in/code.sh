@@ -0,0 +1,3 @@
+#!/usr/bin/env sh
+
+echo "Hello world from sh"
@@ -0,0 +1,9 @@
+import sys
+
+# #region shout
+def shout(name):
+    print(f"HELLO {name.upper()}")
+# #endregion
+
+if __name__ == "__main__":
+    shout(sys.argv[1])
@@ -0,0 +1,4 @@
+def greet(name):
+    print(f"hello {name}")
+def farewell(name):
+    print(f"goodbye {name}")
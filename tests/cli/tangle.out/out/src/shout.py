@@ -0,0 +1,2 @@
+def shout(name):
+    print(f"HELLO {name.upper()}")
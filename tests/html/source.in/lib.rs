@@ -0,0 +1,10 @@
+//! Example library
+
+pub fn parse(input: &str) -> Vec<&str> {
+    // #region split
+    let parts = input
+        .split(',')
+        .map(str::trim);
+    // #endregion
+    parts.collect()
+}